@@ -23,21 +23,21 @@ fn main() {
     for (chart_idx, chart) in sm_file.charts.iter().enumerate() {
         println!("\nConverting chart {}: {} ({})", 
             chart_idx + 1, 
-            chart.difficulty.trim_end_matches(':'),
-            chart.stepstype.trim_end_matches(':')
+            chart.difficulty,
+            chart.stepstype
         );
         
         // Create OsuSettings (you can adjust these values)
-        let settings = OsuSettings {
-            hp: 5.0,  // HP Drain Rate
-            od: 8.0,  // Overall Difficulty
-        };
+        let settings = OsuSettings::new()
+            .hp(5.0) // HP Drain Rate
+            .od(8.0) // Overall Difficulty
+            .version_template("{difficulty} {meter}");
         
         // Convert to .osu format
         match create_basic_osu(&sm_file, chart, &settings) {
             Ok(osu_content) => {
                 // Generate output filename
-                let difficulty_name = chart.difficulty.trim_end_matches(':');
+                let difficulty_name = &chart.difficulty;
                 let sanitized_name = difficulty_name
                     .chars()
                     .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
@@ -0,0 +1,298 @@
+use crate::structs::Chart;
+
+// Notes further apart than this (in MILLISECONDS) are not part of the same
+// jack, trill, roll or stream
+const MAX_GAP_MS: f64 = 250.0;
+// Largest change of the time between rows in a stream, as a share of its first gap
+const STREAM_GAP_TOLERANCE: f64 = 0.1;
+// Shortest trills and jumptrills, in rows, and streams (a measure of 16ths)
+const TRILL_MIN_ROWS: usize = 4;
+const STREAM_MIN_ROWS: usize = 16;
+// Breaks at least this long (in MILLISECONDS) are not playable time, as in
+// the break periods osu! writes by default
+const MIN_BREAK_MS: f64 = 5000.0;
+// Shortest time between density samples, in MILLISECONDS, and most samples
+// in a graph; longer charts are sampled less often
+const MIN_STEP_MS: f64 = 1.0;
+const MAX_SAMPLES: f64 = 100_000.0;
+
+/// Pattern statistics of a chart's judged notes.
+///
+/// Rows are the chart's rows with at least one tap, hold, roll or lift
+/// head; patterns are read across consecutive rows, in time order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternStats {
+    pub notes: u32,
+    pub rows: u32,
+    pub jumps: ChordStats,    // Rows with 2 notes
+    pub hands: ChordStats,    // Rows with 3 notes
+    pub quads: ChordStats,    // Rows with 4 notes or more
+    pub minijacks: Vec<u32>,  // Per column: 2 hits in a row on it
+    pub longjacks: Vec<u32>,  // Per column: 3 hits in a row or more on it
+    pub one_hand_trills: u32, // Single notes alternating between 2 columns of a hand
+    pub rolls: u32,           // Single notes sweeping across the columns in one direction
+    pub jumptrills: u32,      // Jumps alternating between 2 column pairs
+    pub streams: Vec<StreamSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ChordStats {
+    pub count: u32,
+    pub peak_per_second: u32, // Most found within any one second
+}
+
+/// Notes per second over a chart, from `Chart::density_graph`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityGraph {
+    pub samples: Vec<DensitySample>,
+    pub peak_nps: f64,
+    pub average_nps: f64, // Over the time from the first note to the last, less breaks of 5s or more
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensitySample {
+    pub time_ms: f64, // Middle of the window
+    pub nps: f64,
+}
+
+/// A stretch of the chart with nothing to hit or hold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Break {
+    pub start_ms: f64, // End of the note or hold before it
+    pub end_ms: f64,   // Next note
+}
+
+/// Evenly spaced rows, at least `STREAM_MIN_ROWS` of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamSegment {
+    pub start_ms: f64,
+    pub end_ms: f64, // Time of the last row
+    pub rows: u32,
+    pub notes: u32,
+    pub nps: f64, // Notes per second, each row lasting until the next; 0 if the rows are at the same time
+}
+
+impl Chart {
+    /// Counts the chart's chords, jacks, trills, rolls and streams.
+    ///
+    /// Jacks, trills, rolls and streams only join rows at most 250ms apart.
+    /// Columns are split between two hands like in `Chart::difficulty`.
+    pub fn pattern_stats(&self) -> PatternStats {
+        let rows: Vec<(f64, u64)> = self
+            .note_rows(1.0)
+            .into_iter()
+            .map(|(seconds, columns)| (seconds * 1000.0, columns))
+            .collect();
+        let column_count = (self.column_count as usize).clamp(1, 64);
+        let notes = |rows: &[(f64, u64)]| rows.iter().map(|(_, columns)| columns.count_ones()).sum::<u32>();
+
+        let mut stats = PatternStats {
+            notes: notes(&rows),
+            rows: rows.len() as u32,
+            jumps: chord_stats(&rows, |count| count == 2),
+            hands: chord_stats(&rows, |count| count == 3),
+            quads: chord_stats(&rows, |count| count >= 4),
+            minijacks: vec![0; column_count],
+            longjacks: vec![0; column_count],
+            one_hand_trills: 0,
+            rolls: 0,
+            jumptrills: 0,
+            streams: Vec::new(),
+        };
+
+        let left_hand = (1u64 << (column_count / 2)) - 1;
+        let single = |columns: u64| columns.count_ones() == 1;
+        let close = |run: &[(f64, u64)], next: &(f64, u64)| next.0 - run[run.len() - 1].0 <= MAX_GAP_MS;
+        // The columns of the row before the last of `run`, if it has 2 rows
+        let two_back = |run: &[(f64, u64)]| (run.len() >= 2).then(|| run[run.len() - 2].1);
+
+        for column in 0..column_count {
+            let bit = 1u64 << column;
+            let jacks = runs(&rows, 2, |run, next| {
+                close(run, next) && run[run.len() - 1].1 & bit != 0 && next.1 & bit != 0
+            });
+            for run in jacks {
+                if run.len() == 2 {
+                    stats.minijacks[column] += 1;
+                } else {
+                    stats.longjacks[column] += 1;
+                }
+            }
+        }
+
+        stats.one_hand_trills = runs(&rows, TRILL_MIN_ROWS, |run, next| {
+            let last = run[run.len() - 1].1;
+            let same_hand = (last & left_hand == 0) == (next.1 & left_hand == 0);
+            close(run, next)
+                && single(last)
+                && single(next.1)
+                && same_hand
+                && next.1 != last
+                && two_back(run).is_none_or(|columns| columns == next.1)
+        })
+        .len() as u32;
+
+        let roll_rows = column_count.min(4);
+        stats.rolls = runs(&rows, roll_rows, |run, next| {
+            let column = |columns: u64| columns.trailing_zeros() as i32;
+            let last = run[run.len() - 1].1;
+            let step = column(next.1) - column(last);
+            close(run, next)
+                && single(last)
+                && single(next.1)
+                && step != 0
+                && two_back(run).is_none_or(|columns| (column(last) - column(columns)).signum() == step.signum())
+        })
+        .len() as u32;
+
+        stats.jumptrills = runs(&rows, TRILL_MIN_ROWS, |run, next| {
+            let last = run[run.len() - 1].1;
+            close(run, next)
+                && last.count_ones() == 2
+                && next.1.count_ones() == 2
+                && last & next.1 == 0
+                && two_back(run).is_none_or(|columns| columns == next.1)
+        })
+        .len() as u32;
+
+        stats.streams = runs(&rows, STREAM_MIN_ROWS, |run, next| {
+            let gap = next.0 - run[run.len() - 1].0;
+            let first_gap = if run.len() >= 2 { run[1].0 - run[0].0 } else { gap };
+            gap <= MAX_GAP_MS && (gap - first_gap).abs() <= first_gap * STREAM_GAP_TOLERANCE
+        })
+        .into_iter()
+        .map(|run| {
+            let (start_ms, end_ms) = (run[0].0, run[run.len() - 1].0);
+            let row_ms = (end_ms - start_ms) / (run.len() - 1) as f64;
+            StreamSegment {
+                start_ms,
+                end_ms,
+                rows: run.len() as u32,
+                notes: notes(run),
+                nps: if row_ms > 0.0 { notes(run) as f64 / (run.len() as f64 * row_ms / 1000.0) } else { 0.0 },
+            }
+        })
+        .collect();
+        stats
+    }
+
+    /// Samples the chart's notes per second every `step_ms`, from its first
+    /// note to its last, each sample counting the notes in the `window_ms`
+    /// around it. Chords count each of their notes.
+    ///
+    /// The step is at least 1ms, and is raised so there are at most 100,000
+    /// samples (more than 1ms apart only past 100 seconds, such as across a
+    /// very long stop).
+    pub fn density_graph(&self, window_ms: f64, step_ms: f64) -> DensityGraph {
+        let times: Vec<f64> = self
+            .note_rows(1.0)
+            .into_iter()
+            .flat_map(|(seconds, columns)| std::iter::repeat_n(seconds * 1000.0, columns.count_ones() as usize))
+            .collect();
+        let (Some(&first), Some(&last)) = (times.first(), times.last()) else {
+            return DensityGraph {
+                samples: Vec::new(),
+                peak_nps: 0.0,
+                average_nps: 0.0,
+            };
+        };
+
+        let mut samples = Vec::new();
+        if window_ms > 0.0 && step_ms > 0.0 {
+            // Times are sorted: the window is the range between two partition points
+            let step_ms = step_ms.max(MIN_STEP_MS).max((last - first) / (MAX_SAMPLES - 1.0));
+            let count = ((last - first) / step_ms).floor() as usize + 1;
+            for idx in 0..count {
+                let time_ms = first + idx as f64 * step_ms;
+                let start = times.partition_point(|time| *time < time_ms - window_ms / 2.0);
+                let end = times.partition_point(|time| *time < time_ms + window_ms / 2.0);
+                samples.push(DensitySample {
+                    time_ms,
+                    nps: (end - start) as f64 / (window_ms / 1000.0),
+                });
+            }
+        }
+        let peak_nps = samples.iter().map(|sample| sample.nps).fold(0.0, f64::max);
+        let breaks_ms: f64 = self.breaks(MIN_BREAK_MS).iter().map(|gap| gap.end_ms - gap.start_ms).sum();
+        let playable_ms = last - first - breaks_ms;
+        let average_nps = if playable_ms > 0.0 { times.len() as f64 / (playable_ms / 1000.0) } else { 0.0 };
+        DensityGraph {
+            samples,
+            peak_nps,
+            average_nps,
+        }
+    }
+
+    /// Gaps of at least `min_length_ms` between the end of a note or hold
+    /// and the next note.
+    pub fn breaks(&self, min_length_ms: f64) -> Vec<Break> {
+        // (start, end) of everything to hit or hold, by start
+        let mut busy: Vec<(f64, f64)> = self
+            .note_rows(1.0)
+            .into_iter()
+            .map(|(seconds, _)| (seconds * 1000.0, seconds * 1000.0))
+            .chain(self.holds.iter().map(|hold| (hold.start_ms, hold.end_ms)))
+            .collect();
+        busy.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut breaks = Vec::new();
+        let mut busy_until = f64::NEG_INFINITY;
+        for (start, end) in busy {
+            if busy_until.is_finite() && start - busy_until >= min_length_ms {
+                breaks.push(Break {
+                    start_ms: busy_until,
+                    end_ms: start,
+                });
+            }
+            busy_until = busy_until.max(end);
+        }
+        breaks
+    }
+}
+
+/// Count and 1-second peak of the rows whose note count passes `kind`.
+fn chord_stats(rows: &[(f64, u64)], kind: impl Fn(u32) -> bool) -> ChordStats {
+    let times: Vec<f64> = rows
+        .iter()
+        .filter(|(_, columns)| kind(columns.count_ones()))
+        .map(|(time, _)| *time)
+        .collect();
+    let mut peak = 0;
+    let mut first = 0;
+    for (last, time) in times.iter().enumerate() {
+        while time - times[first] >= 1000.0 {
+            first += 1;
+        }
+        peak = peak.max(last - first + 1);
+    }
+    ChordStats {
+        count: times.len() as u32,
+        peak_per_second: peak as u32,
+    }
+}
+
+/// Longest runs of consecutive `rows`, each row passing `extends` given the
+/// run before it, with at least `min_rows` rows. A row that breaks a run
+/// starts the next one with the row before it when it can.
+fn runs(
+    rows: &[(f64, u64)],
+    min_rows: usize,
+    extends: impl Fn(&[(f64, u64)], &(f64, u64)) -> bool,
+) -> Vec<&[(f64, u64)]> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    for idx in 1..=rows.len() {
+        if idx < rows.len() && extends(&rows[start..idx], &rows[idx]) {
+            continue;
+        }
+        if idx - start >= min_rows {
+            found.push(&rows[start..idx]);
+        }
+        start = if idx < rows.len() && idx - start > 1 && extends(&rows[idx - 1..idx], &rows[idx]) {
+            idx - 1
+        } else {
+            idx
+        };
+    }
+    found
+}
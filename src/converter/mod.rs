@@ -1,2 +1,5 @@
 pub mod osu;
-pub use osu::create_basic_osu;
\ No newline at end of file
+pub mod rate;
+pub mod sm;
+pub use osu::create_basic_osu;
+pub use sm::from_osu;
\ No newline at end of file
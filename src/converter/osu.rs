@@ -1,131 +1,178 @@
-use crate::structs::{SmFile, OsuSettings, Chart};
+use crate::error::Error;
+use crate::structs::{
+    Chart, HoldNote, LongNoteMode, MineMode, NoteType, OsuBeatmap, OsuEvent, OsuHitObject,
+    OsuHitObjectKind, OsuSettings, OsuTimingPoint, PreviewTime, ScrollSpeedMode, SmFile,
+};
+use crate::timing::TimingData;
+use crate::utils::log_debug;
 
-// StepMania row system constants (must match decode.rs)
-const ROWS_PER_BEAT: f64 = 48.0;  // 1 beat = 48 rows (for 4/4 time)
-pub fn create_basic_osu(sm_file: &SmFile, chart: &Chart, settings: &OsuSettings) -> Result<String, String> {
-    // This is a placeholder - should use rosu-map instead
-    let mut osu = String::new();
-    
-    osu.push_str("osu file format v14\n");
-    osu.push_str("\n");
-    osu.push_str("[General]\n");
-    osu.push_str("AudioFilename: ");
-    osu.push_str(&sm_file.metadata.music);
-    osu.push_str("\n");
-    osu.push_str("AudioLeadIn: 0\n");
-    osu.push_str("PreviewTime: -1\n");
-    osu.push_str("Countdown: 0\n");
-    osu.push_str("SampleSet: Normal\n");
-    osu.push_str("StackLeniency: 0.7\n");
-    osu.push_str("Mode: 3\n"); // osu!mania
-    osu.push_str("LetterboxInBreaks: 0\n");
-    osu.push_str("WidescreenStoryboard: 0\n");
-    osu.push_str("\n");
-    osu.push_str("[Editor]\n");
-    osu.push_str("\n");
-    osu.push_str("[Metadata]\n");
-    osu.push_str("Title:");
-    osu.push_str(&sm_file.metadata.title);
-    osu.push_str("\n");
-    osu.push_str("TitleUnicode:");
-    osu.push_str(&sm_file.metadata.title);
-    osu.push_str("\n");
-    osu.push_str("Artist:");
-    osu.push_str(&sm_file.metadata.artist);
-    osu.push_str("\n");
-    osu.push_str("ArtistUnicode:");
-    osu.push_str(&sm_file.metadata.artist);
-    osu.push_str("\n");
-    osu.push_str("Creator:");
-    osu.push_str(&sm_file.metadata.credit);
-    osu.push_str("\n");
-    osu.push_str("Version:");
-    osu.push_str(&chart.difficulty);
-    osu.push_str("\n");
-    osu.push_str("Source:\n");
-    osu.push_str("Tags: rOtterna\n");
-    osu.push_str("BeatmapID: 0\n");
-    osu.push_str("BeatmapSetID: -1\n");
-    osu.push_str("\n");
-    osu.push_str("[Difficulty]\n");
-    osu.push_str(&format!("HPDrainRate: {}\n", settings.hp));
-    osu.push_str("CircleSize: 4\n");
-    osu.push_str(&format!("OverallDifficulty: {}\n", settings.od));
-    osu.push_str("ApproachRate: 5\n");
-    osu.push_str("SliderMultiplier: 1.4\n");
-    osu.push_str("SliderTickRate: 1\n");
-    osu.push_str("\n");
-    osu.push_str("[Events]\n");
-    osu.push_str("//Background and Video events\n");
-    if !sm_file.metadata.background.is_empty() {
-        osu.push_str("0,0,\"");
-        osu.push_str(&sm_file.metadata.background);
-        osu.push_str("\",0,0\n");
+// Inherited beat length for a 0.01x slider velocity, the slowest osu! allows
+pub(crate) const STOP_BEAT_LENGTH: f64 = -10000.0;
+// Time kept clear between a break period and the notes around it, as the osu! editor does
+const BREAK_MARGIN_MS: f64 = 200.0;
+
+/// Converts `chart` to .osu text. See `to_osu`.
+pub fn create_basic_osu(sm_file: &SmFile, chart: &Chart, settings: &OsuSettings) -> Result<String, Error> {
+    to_osu(sm_file, chart, settings).map(|osu| osu.to_string())
+}
+
+/// Builds the osu!mania beatmap of `chart`, with one key per column unless
+/// `settings` overrides the key count. At a rate other than 1.0 the song
+/// and chart are first converted with `SmFile::with_rate`; the error is
+/// `Error::UnsupportedRate` for a rate outside 0.5 to 3.0.
+pub fn to_osu(sm_file: &SmFile, chart: &Chart, settings: &OsuSettings) -> Result<OsuBeatmap, Error> {
+    let rated;
+    let (sm_file, chart) = if settings.rate == 1.0 {
+        (sm_file, chart)
+    } else {
+        rated = (sm_file.with_rate(settings.rate)?, chart.with_rate(settings.rate)?);
+        (&rated.0, &rated.1)
+    };
+
+    let mut osu = OsuBeatmap::new();
+    let metadata = &sm_file.metadata;
+    let chart_columns = if chart.column_count > 0 { chart.column_count } else { 4 }; // Default to 4 columns if not set
+    let column_count = settings.key_count.unwrap_or(chart_columns);
+
+    osu.general.audio_filename = audio_filename(&settings.audio_filename_template, &metadata.music, settings.rate);
+    osu.general.audio_lead_in = settings.audio_lead_in;
+    osu.general.countdown = 0;
+    osu.general.mode = 3; // osu!mania
+    match settings.preview_time {
+        PreviewTime::SampleStart if metadata.sample_start > 0.0 => {
+            osu.general.preview_time = metadata.sample_start.round() as i32;
+        }
+        PreviewTime::At(time_ms) => osu.general.preview_time = time_ms,
+        _ => {}
     }
-    osu.push_str("//Break Periods\n");
-    osu.push_str("//Storyboard Layer 0 (Background)\n");
-    osu.push_str("//Storyboard Layer 1 (Fail)\n");
-    osu.push_str("//Storyboard Layer 2 (Pass)\n");
-    osu.push_str("//Storyboard Layer 3 (Foreground)\n");
-    osu.push_str("//Storyboard Sound Samples\n");
-    osu.push_str("\n");
-    
+
+    // osu! wants romanized Title/Artist, the original script goes in the Unicode fields
+    let romanized = |translit: &str, text: &str| if translit.is_empty() { text } else { translit }.to_string();
+    osu.metadata.title = romanized(&metadata.title_translit, &metadata.title);
+    osu.metadata.title_unicode = metadata.title.clone();
+    osu.metadata.artist = romanized(&metadata.artist_translit, &metadata.artist);
+    osu.metadata.artist_unicode = metadata.artist.clone();
+    osu.metadata.creator = settings.creator.clone().unwrap_or_else(|| metadata.credit.clone());
+    osu.metadata.version = version_name(&settings.version_template, chart);
+    if settings.rate != 1.0 {
+        osu.metadata.version.push_str(&format!(" ({}x)", settings.rate));
+    }
+    osu.metadata.source = settings.source.clone();
+    osu.metadata.tags = metadata
+        .genre
+        .split_whitespace()
+        .chain([chart.stepstype.as_str()])
+        .chain(settings.tags.iter().map(String::as_str))
+        .map(str::to_string)
+        .collect();
+
+    osu.difficulty.hp_drain_rate = settings.hp;
+    osu.difficulty.circle_size = column_count as f64; // Key count in osu!mania
+    osu.difficulty.overall_difficulty = settings.od;
+
+    if !metadata.background.is_empty() {
+        osu.events.push(OsuEvent::Background {
+            filename: metadata.background.clone(),
+            x: 0,
+            y: 0,
+        });
+    }
+    for gap in chart.breaks(settings.min_break_ms) {
+        let start_time = (gap.start_ms + BREAK_MARGIN_MS).round() as i32;
+        let end_time = (gap.end_ms - BREAK_MARGIN_MS).round() as i32;
+        if end_time > start_time {
+            osu.events.push(OsuEvent::Break { start_time, end_time });
+        }
+    }
+
     // Generate timing points for all BPM changes
-    osu.push_str("[TimingPoints]\n");
-    
-    println!("[create_basic_osu] Found {} BPM change(s)", sm_file.bpms.len());
+    // .ssc charts with split timing override the song timing
+    let timing = TimingData::for_chart(sm_file, chart);
     
-    // Offset is already in milliseconds (converted in decode.rs)
-    let offset_ms = sm_file.offset as i32;
+    log_debug!("[to_osu] Found {} timing segment(s)", timing.segments().len());
+
+    // #SCROLLS ratios as SV multipliers, by time; they go first so a stop
+    // starting at the same time overrides them
+    let scrolls: Vec<(i32, f64)> = match settings.scroll_speed {
+        ScrollSpeedMode::StopsAndScrolls => {
+            let segments = chart.timing.as_ref().map_or(&sm_file.segments, |timing| &timing.segments);
+            segments
+                .scrolls
+                .iter()
+                .map(|(row, ratio)| (timing.time_at_row(*row).round() as i32, *ratio))
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    let scroll_at = |time_ms: i32| {
+        scrolls
+            .iter()
+            .take_while(|(scroll_ms, _)| *scroll_ms <= time_ms)
+            .last()
+            .map_or(1.0, |(_, ratio)| *ratio)
+    };
+    for (time_ms, ratio) in &scrolls {
+        osu.timing_points.push(timing_point(*time_ms, scroll_beat_length(*ratio), false));
+    }
     
-    if sm_file.bpms.is_empty() {
-        // Default BPM if none found
-        println!("[create_basic_osu] No BPMs found, using default 120 BPM");
-        osu.push_str(&format!("{},{},4,2,0,100,1,0\n", offset_ms, 500.0)); // 120 BPM = 500ms per beat
-    } else {
-        // Generate a timing point for each BPM change
-        // BPMs are stored as (row, bpm) pairs, need to convert rows to beats and then to time
-        let mut current_time_ms = 0.0;
-        let mut current_row = 0.0;
-        let mut current_bpm = sm_file.bpms[0].1;
+    // Generate a timing point for each BPM change, plus a pause/resume
+    // pair for each stop or delay
+    let mut current_bpm = None;
+    for segment in timing.segments() {
+        // Warped rows take no time, the segment after the warp starts at the same time
+        if segment.warped && segment.stop_ms == 0.0 && segment.delay_ms == 0.0 {
+            continue;
+        }
+        let time_ms = segment.time_ms.round() as i32;
+        // Calculate beat duration in milliseconds (60000ms / BPM); negative
+        // BPMs are only found in warps
+        let beat_duration_ms = 60000.0 / segment.bpm.abs();
         
-        for (idx, (bpm_row, bpm)) in sm_file.bpms.iter().enumerate() {
-            // Calculate time elapsed from previous BPM change
-            if idx > 0 {
-                let rows_elapsed = bpm_row - current_row;
-                let beats_elapsed = rows_elapsed / ROWS_PER_BEAT;
-                let time_elapsed_ms = (beats_elapsed / current_bpm) * 60000.0;
-                current_time_ms += time_elapsed_ms;
-            }
+        // A warped segment is only here for its pause, the BPM after the warp sets the grid
+        if !segment.warped && current_bpm != Some(segment.bpm) {
+            log_debug!("[to_osu] BPM change at row {} (beat {}) ({}ms): {} BPM ({}ms per beat)", 
+                segment.row, segment.row.beats(), time_ms, segment.bpm, beat_duration_ms);
             
-            // Apply offset: timing point starts at offset + calculated time
-            let time_ms = (current_time_ms + sm_file.offset) as i32;
-            
-            // Calculate beat duration in milliseconds (60000ms / BPM)
-            let beat_duration_ms = 60000.0 / bpm;
-            
-            // Convert row to beat for display
-            let bpm_beat = bpm_row / ROWS_PER_BEAT;
-            
-            println!("[create_basic_osu] BPM change at row {} (beat {}) ({}ms): {} BPM ({}ms per beat)", 
-                bpm_row, bpm_beat, time_ms, bpm, beat_duration_ms);
-            
-             // Format: time,beatLength,meter,sampleSet,sampleIndex,volume,uninherited,effects
-             // uninherited = 1 means this is a timing point (not inherited)
-             // beatLength can have decimals, don't round it
-            osu.push_str(&format!("{},{},4,2,0,100,1,0\n", time_ms, beat_duration_ms));
+            // beatLength can have decimals, don't round it
+            osu.timing_points.push(timing_point(time_ms, beat_duration_ms, true));
+            current_bpm = Some(segment.bpm);
+            // An uninherited point resets the SV to 1x
+            if scroll_at(time_ms) != 1.0 {
+                osu.timing_points.push(timing_point(time_ms, scroll_beat_length(scroll_at(time_ms)), false));
+            }
+        }
+        
+        let pause_ms = segment.delay_ms + segment.stop_ms;
+        if pause_ms > 0.0 && settings.scroll_speed != ScrollSpeedMode::None {
+            // Pause: slowest possible scroll (0.01x) while the stop lasts
+            osu.timing_points.push(timing_point(time_ms, STOP_BEAT_LENGTH, false));
             
-            // Update for next iteration
-            current_row = *bpm_row;
-            current_bpm = *bpm;
+            // Resume: re-anchor the beat grid at the current BPM once the stop ends
+            let resume_ms = (segment.time_ms + pause_ms).round() as i32;
+            osu.timing_points.push(timing_point(resume_ms, beat_duration_ms, true));
+            if scroll_at(resume_ms) != 1.0 {
+                osu.timing_points.push(timing_point(resume_ms, scroll_beat_length(scroll_at(resume_ms)), false));
+            }
         }
     }
+    // Uninherited points first at equal times, as the osu! editor sorts them
+    osu.timing_points.sort_by(|a, b| {
+        a.time
+            .partial_cmp(&b.time)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.uninherited.cmp(&a.uninherited))
+    });
     
-    osu.push_str("\n");
-    
-    osu.push_str("[HitObjects]\n");
     // Convert notes
-    let column_count = if chart.column_count > 0 { chart.column_count } else { 4 }; // Default to 4 columns if not set
+    // Holds per column, in time order, so each head can find its tail
+    let mut holds_by_column: Vec<Vec<&HoldNote>> = Vec::new();
+    for hold in &chart.holds {
+        if holds_by_column.len() <= hold.column {
+            holds_by_column.resize(hold.column + 1, Vec::new());
+        }
+        holds_by_column[hold.column].push(hold);
+    }
+    let mut next_hold = vec![0usize; holds_by_column.len()];
     
     for measure in chart.measures.iter() {
         // Convert note row to osu format
@@ -133,18 +180,110 @@ pub fn create_basic_osu(sm_file: &SmFile, chart: &Chart, settings: &OsuSettings)
             let time_ms = beat.time;
             
             for (note_idx, note) in beat.notes.iter().enumerate() {
-                if *note {
-                    // Calculate column position: osu!mania uses 512 pixels width, divide by column count
-                    let column = (note_idx as f64 + 0.5) * 512.0 / column_count as f64;
-                    // Format: x,y,time,type,hitSound,objectParams,hitSample
-                    // For osu!mania: x is column position, y is 192 (center), type 1 = circle
-                    // Apply offset: notes are already calculated from 0, add offset to match timing point
-                    let note_time_ms = (time_ms + sm_file.offset) as i32;
-                    osu.push_str(&format!("{},{},{},1,0,0:0:0:0:\n", column as i32, 192, note_time_ms));
+                // Tails, fakes and keysounds have no osu!mania equivalent, nor do mines unless
+                // they are played as taps
+                let mine_tap = *note == NoteType::Mine && settings.mines == MineMode::Taps;
+                if !note.is_note() && !mine_tap {
+                    continue;
+                }
+                
+                // Calculate column position: osu!mania uses 512 pixels width, divide by column count
+                let column = (note_idx as f64 + 0.5) * 512.0 / column_count as f64;
+                // Note times already include the offset (see decode.rs)
+                let note_time_ms = time_ms.round() as i32;
+                
+                // Unterminated heads have no entry in chart.holds and fall back to taps
+                let hold = match note {
+                    NoteType::HoldHead | NoteType::RollHead => holds_by_column
+                        .get(note_idx)
+                        .and_then(|holds| holds.get(next_hold[note_idx]))
+                        .filter(|hold| hold.start_ms == time_ms)
+                        .copied(),
+                    _ => None,
+                };
+                if hold.is_some() {
+                    next_hold[note_idx] += 1;
                 }
+                // Notes in warps and #FAKES ranges are never judged, columns past
+                // the key count are dropped
+                if !beat.judged || note_idx >= column_count as usize {
+                    continue;
+                }
+                
+                let (object_type, kind) = match hold {
+                    // type 128 = osu!mania hold note
+                    Some(hold) if is_long_note(hold, settings) => {
+                        let end_time = hold.end_ms.round() as i32;
+                        (128, OsuHitObjectKind::Hold { end_time })
+                    }
+                    // type 1 = circle
+                    _ => (1, OsuHitObjectKind::Circle),
+                };
+                // For osu!mania: x is column position, y is 192 (center)
+                osu.hit_objects.push(OsuHitObject {
+                    x: column as i32,
+                    y: 192,
+                    time: note_time_ms,
+                    object_type,
+                    hit_sound: 0,
+                    kind,
+                    hit_sample: "0:0:0:0:".to_string(),
+                });
             }
         }
     }
     
     Ok(osu)
 }
+
+/// Whether `hold` stays a long note; otherwise it becomes a tap on its head.
+fn is_long_note(hold: &HoldNote, settings: &OsuSettings) -> bool {
+    let kept = match settings.long_notes {
+        LongNoteMode::All => true,
+        LongNoteMode::HoldsOnly => !hold.is_roll,
+        LongNoteMode::None => false,
+    };
+    kept && hold.end_ms - hold.start_ms >= settings.min_long_note_ms
+}
+
+/// `template` with the chart's `{difficulty}`, `{meter}`, `{description}`,
+/// `{stepstype}` and `{chart_name}`.
+fn version_name(template: &str, chart: &Chart) -> String {
+    template
+        .replace("{difficulty}", &chart.difficulty)
+        .replace("{meter}", &chart.meter.to_string())
+        .replace("{description}", &chart.description)
+        .replace("{stepstype}", &chart.stepstype)
+        .replace("{chart_name}", &chart.chart_name)
+}
+
+/// `template` with the song's `{music}` file, its `{stem}` and `{extension}`,
+/// and the `{rate}`.
+fn audio_filename(template: &str, music: &str, rate: f64) -> String {
+    let (stem, extension) = music.rsplit_once('.').unwrap_or((music, ""));
+    template
+        .replace("{music}", music)
+        .replace("{stem}", stem)
+        .replace("{extension}", extension)
+        .replace("{rate}", &rate.to_string())
+}
+
+/// Inherited beat length of a scroll `ratio`: -100 / ratio, clamped to the
+/// slowest SV for a stopped scroll.
+fn scroll_beat_length(ratio: f64) -> f64 {
+    if ratio > 0.0 { (-100.0 / ratio).max(STOP_BEAT_LENGTH) } else { STOP_BEAT_LENGTH }
+}
+
+/// Timing point with the converter's defaults: 4/4, soft samples, full volume.
+fn timing_point(time_ms: i32, beat_length: f64, uninherited: bool) -> OsuTimingPoint {
+    OsuTimingPoint {
+        time: time_ms as f64,
+        beat_length,
+        meter: 4,
+        sample_set: 2,
+        sample_index: 0,
+        volume: 100,
+        uninherited,
+        effects: 0,
+    }
+}
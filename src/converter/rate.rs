@@ -0,0 +1,99 @@
+use crate::error::Error;
+use crate::structs::{Chart, ChartTiming, Diagnostic, SmFile, TimingSegments};
+
+// Music rates files can be converted to
+pub const MIN_RATE: f64 = 0.5;
+pub const MAX_RATE: f64 = 3.0;
+
+impl SmFile {
+    /// The song played at `rate` (0.5 to 3.0) times its speed: BPMs are
+    /// multiplied by `rate` and times, stop and delay lengths, #OFFSET and
+    /// the sample start and length divided by it. Rows stay the same, and so
+    /// does the audio file, which must be resampled separately.
+    pub fn with_rate(&self, rate: f64) -> Result<SmFile, Error> {
+        check_rate(rate)?;
+        let mut rated = self.clone();
+        rated.offset /= rate;
+        rated.bpms = scale_bpms(&self.bpms, rate);
+        rated.stops = scale_seconds(&self.stops, rate);
+        rated.segments = self.segments.with_rate(rate);
+        rated.metadata.sample_start /= rate;
+        rated.metadata.sample_length /= rate;
+        rated.charts = self.charts.iter().map(|chart| chart.scaled(rate)).collect();
+        Ok(rated)
+    }
+}
+
+impl Chart {
+    /// The chart played at `rate` (0.5 to 3.0): note and hold times and the
+    /// chart's split timing are scaled like `SmFile::with_rate` scales a song.
+    pub fn with_rate(&self, rate: f64) -> Result<Chart, Error> {
+        check_rate(rate)?;
+        Ok(self.scaled(rate))
+    }
+
+    fn scaled(&self, rate: f64) -> Chart {
+        let mut rated = self.clone();
+        rated.timing = self.timing.as_ref().map(|timing| ChartTiming {
+            offset: timing.offset / rate,
+            bpms: scale_bpms(&timing.bpms, rate),
+            stops: scale_seconds(&timing.stops, rate),
+            segments: timing.segments.with_rate(rate),
+        });
+        // Every time is measured from the start of the audio, so it scales as a whole
+        for measure in &mut rated.measures {
+            measure.start_time /= rate;
+            for beat in &mut measure.beats {
+                beat.time /= rate;
+            }
+        }
+        for hold in &mut rated.holds {
+            hold.start_ms /= rate;
+            hold.end_ms /= rate;
+        }
+        for diagnostic in &mut rated.diagnostics {
+            match diagnostic {
+                Diagnostic::UnterminatedHold { start_ms, .. } => *start_ms /= rate,
+                Diagnostic::OrphanTail { time_ms, .. } | Diagnostic::OverlappingNote { time_ms, .. } => *time_ms /= rate,
+                Diagnostic::OffSnapNote { time_ms, snapped_ms, .. } => {
+                    *time_ms /= rate;
+                    *snapped_ms /= rate;
+                }
+            }
+        }
+        rated
+    }
+}
+
+impl TimingSegments {
+    /// Delays and speed changes timed in seconds are shortened by `rate`;
+    /// everything measured in beats is unchanged.
+    fn with_rate(&self, rate: f64) -> TimingSegments {
+        let mut rated = self.clone();
+        rated.delays = scale_seconds(&self.delays, rate);
+        for speed in rated.speeds.iter_mut().filter(|speed| speed.in_seconds) {
+            speed.duration /= rate;
+        }
+        rated
+    }
+}
+
+fn check_rate(rate: f64) -> Result<(), Error> {
+    if (MIN_RATE..=MAX_RATE).contains(&rate) {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedRate {
+            rate,
+            min: MIN_RATE,
+            max: MAX_RATE,
+        })
+    }
+}
+
+fn scale_bpms<T: Copy>(bpms: &[(T, f64)], rate: f64) -> Vec<(T, f64)> {
+    bpms.iter().map(|(row, bpm)| (*row, bpm * rate)).collect()
+}
+
+fn scale_seconds<T: Copy>(lengths: &[(T, f64)], rate: f64) -> Vec<(T, f64)> {
+    lengths.iter().map(|(row, seconds)| (*row, seconds / rate)).collect()
+}
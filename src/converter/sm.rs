@@ -0,0 +1,302 @@
+use crate::converter::osu::STOP_BEAT_LENGTH;
+use crate::error::Error;
+use crate::structs::{
+    Beat, Chart, Diagnostic, Measure, NoteType, OsuBeatmap, OsuEvent, OsuHitObjectKind, SmFile,
+    TimingSegments,
+};
+use crate::timing::{ROWS_PER_BEAT, ROWS_PER_MEASURE, Row, Snap, TimingData};
+use crate::utils::log_debug;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Largest time shift (in MILLISECONDS) accepted to put a note on a snap.
+// osu! stores integer times, so notes on the grid are within 0.5ms of it.
+const SNAP_TOLERANCE_MS: f64 = 2.0;
+
+/// Converts an osu!mania beatmap into a StepMania song with one chart.
+///
+/// Uninherited timing points become #BPMS and the pauses `to_osu` writes for
+/// stops become #STOPS (see `set_timing`). Notes and long notes are put on the
+/// coarsest snap within `SNAP_TOLERANCE_MS`; notes no snap reaches are moved
+/// to the nearest 192nd no other note is on. Off-snap notes, and notes left
+/// out because another note is on their row and column, are reported in
+/// `Chart::diagnostics`.
+pub fn from_osu(beatmap: &OsuBeatmap) -> Result<SmFile, Error> {
+    if beatmap.general.mode != 3 {
+        return Err(Error::UnsupportedOsuMap {
+            reason: format!("mode {} is not osu!mania", beatmap.general.mode),
+        });
+    }
+    let key_count = beatmap.key_count();
+    let stepstype = Chart::stepstype_for(key_count).ok_or_else(|| Error::UnsupportedOsuMap {
+        reason: format!("no StepMania stepstype has {} columns", key_count),
+    })?;
+
+    let mut sm = SmFile::new();
+    let metadata = &beatmap.metadata;
+    // StepMania titles may be in any script, with a romanized transliteration
+    (sm.metadata.title, sm.metadata.title_translit) = with_translit(&metadata.title_unicode, &metadata.title);
+    (sm.metadata.artist, sm.metadata.artist_translit) = with_translit(&metadata.artist_unicode, &metadata.artist);
+    sm.metadata.credit = metadata.creator.clone();
+    sm.metadata.music = beatmap.general.audio_filename.clone();
+    if let Some(background) = beatmap.events.iter().find_map(|event| match event {
+        OsuEvent::Background { filename, .. } => Some(filename.clone()),
+        _ => None,
+    }) {
+        sm.metadata.background = background;
+    }
+    if beatmap.general.preview_time >= 0 {
+        sm.metadata.sample_start = beatmap.general.preview_time as f64;
+    }
+
+    set_timing(&mut sm, beatmap)?;
+    let timing = TimingData::from_sm(&sm);
+
+    let mut chart = Chart::new();
+    chart.stepstype = stepstype.to_string();
+    chart.description = metadata.version.clone();
+    chart.difficulty = "Edit".to_string();
+    chart.column_count = key_count;
+
+    let (note_rows, off_snap) = note_rows(beatmap, &timing);
+    let mut row_of = |time: i32, column: usize| {
+        let row = note_rows[&time];
+        if off_snap.contains(&time) {
+            chart.diagnostics.push(Diagnostic::OffSnapNote {
+                column,
+                time_ms: time as f64,
+                snapped_ms: timing.time_at_row(row),
+            });
+        }
+        row
+    };
+
+    // Note lines by row, one entry per column
+    let mut rows: BTreeMap<Row, Vec<NoteType>> = BTreeMap::new();
+    let mut overlapping = Vec::new();
+    for object in &beatmap.hit_objects {
+        let column = object.column(key_count);
+        let head = row_of(object.time, column);
+        let tail = match object.kind {
+            OsuHitObjectKind::Hold { end_time } => Some(row_of(end_time, column)),
+            _ => None,
+        };
+
+        // A note on a cell another note took is left out, with its tail
+        let free = |row: &Row| rows.get(row).is_none_or(|line| line[column] == NoteType::Empty);
+        if !free(&head) || tail.is_some_and(|tail| tail > head && !free(&tail)) {
+            overlapping.push(Diagnostic::OverlappingNote {
+                column,
+                time_ms: object.time as f64,
+            });
+            continue;
+        }
+        let mut place = |row: Row, note: NoteType| {
+            rows.entry(row).or_insert_with(|| vec![NoteType::Empty; key_count as usize])[column] = note;
+        };
+        match tail {
+            Some(tail) if tail > head => {
+                place(head, NoteType::HoldHead);
+                place(tail, NoteType::Tail);
+            }
+            // Long notes too short to survive quantization; sliders and
+            // spinners are not mania objects: keep their start
+            _ => place(head, NoteType::Tap),
+        }
+    }
+    chart.diagnostics.extend(overlapping);
+
+    let measure_count = rows.keys().last().map_or(1, |row| row.measure() as usize + 1);
+    chart.measures = (0..measure_count)
+        .map(|measure_idx| Measure {
+            beats: Vec::new(),
+            start_time: timing.time_at_row(Row::in_measure(measure_idx, 0, 1)),
+        })
+        .collect();
+    for (row, notes) in rows {
+        chart.measures[row.measure() as usize].beats.push(Beat {
+            row,
+            time: timing.time_at_row(row),
+            notes,
+            judged: true,
+        });
+    }
+    chart.pair_holds();
+
+    sm.charts.push(chart);
+    Ok(sm)
+}
+
+/// (text, transliteration) from an osu! unicode field and its romanized one.
+fn with_translit(unicode: &str, romanized: &str) -> (String, String) {
+    if unicode.is_empty() || unicode == romanized {
+        (romanized.to_string(), String::new())
+    } else {
+        (unicode.to_string(), romanized.to_string())
+    }
+}
+
+/// Sets #OFFSET, #BPMS, #STOPS and #DELAYS from the timing points. Beat 0 is
+/// put whole measures before the first timing point, so no note has a
+/// negative row.
+///
+/// Each uninherited point starts its BPM on the row it falls on: the
+/// coarsest snap within `SNAP_TOLERANCE_MS`, or else the nearest thousandth
+/// of a beat. An uninherited point that resumes the BPM after a pause point
+/// (the 0.01x scroll `to_osu` writes for stops) ends a stop that starts at
+/// the pause, or a delay if notes are on the resume and none on the pause.
+fn set_timing(sm: &mut SmFile, beatmap: &OsuBeatmap) -> Result<(), Error> {
+    let mut points: Vec<(f64, f64, bool)> = beatmap
+        .timing_points
+        .iter()
+        .filter(|point| match point.uninherited {
+            true => point.beat_length > 0.0,
+            false => point.beat_length <= STOP_BEAT_LENGTH,
+        })
+        .map(|point| (point.time, point.beat_length, point.uninherited))
+        .collect();
+    points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let Some(first) = points.iter().position(|(_, _, uninherited)| *uninherited) else {
+        return Err(Error::UnsupportedOsuMap {
+            reason: "no uninherited timing point".to_string(),
+        });
+    };
+    let (first_time, first_beat_length, _) = points[first];
+
+    let first_object = beatmap
+        .hit_objects
+        .iter()
+        .map(|object| object.time as f64)
+        .fold(first_time, f64::min);
+    let measure_ms = first_beat_length * ROWS_PER_MEASURE / ROWS_PER_BEAT;
+    let lead_measures = ((first_time - first_object) / measure_ms).ceil();
+    sm.offset = -(first_time - lead_measures * measure_ms);
+
+    let has_notes_at = |time: f64| {
+        beatmap
+            .hit_objects
+            .iter()
+            .any(|object| (object.time as f64 - time).abs() <= SNAP_TOLERANCE_MS)
+    };
+    let mut bpms = vec![(Row::ZERO, 60000.0 / first_beat_length)];
+    let (mut stops, mut delays) = (Vec::new(), Vec::new());
+    // The row of the last point, and the time StepMania plays it at
+    let mut anchor = (Row::from_rows((lead_measures * ROWS_PER_MEASURE) as i64), first_time);
+    let mut beat_length = first_beat_length;
+    let mut pause = None;
+    for &(time, length, uninherited) in &points[first + 1..] {
+        if !uninherited {
+            pause = Some(time);
+            continue;
+        }
+        match pause.take() {
+            Some(start) if length == beat_length && start < time => {
+                let row = point_row(anchor, beat_length, start);
+                let seconds = (time - start) / 1000.0;
+                if has_notes_at(time) && !has_notes_at(start) {
+                    delays.push((row, seconds));
+                } else {
+                    stops.push((row, seconds));
+                }
+                anchor = (row, time_at(anchor, beat_length, row) + seconds * 1000.0);
+            }
+            _ => {
+                let row = point_row(anchor, beat_length, time);
+                if length != beat_length {
+                    match bpms.last_mut() {
+                        Some(last) if last.0 == row => {
+                            log_debug!("[from_osu] Timing point at {}ms replaces the one on row {}", time, row);
+                            last.1 = 60000.0 / length;
+                        }
+                        _ => bpms.push((row, 60000.0 / length)),
+                    }
+                }
+                anchor = (row, time_at(anchor, beat_length, row));
+                beat_length = length;
+            }
+        }
+    }
+
+    sm.bpms = bpms;
+    sm.stops = stops;
+    sm.segments = TimingSegments::new();
+    sm.segments.delays = delays;
+    Ok(())
+}
+
+/// Time StepMania plays `row` at, `beat_length` ms per beat after `anchor`.
+fn time_at(anchor: (Row, f64), beat_length: f64, row: Row) -> f64 {
+    anchor.1 + (row - anchor.0).beats() * beat_length
+}
+
+/// Row a timing point at `time_ms` falls on, `beat_length` ms per beat after
+/// `anchor`: the coarsest snap within `SNAP_TOLERANCE_MS`, or else the
+/// nearest thousandth of a beat, which #BPMS and #STOPS write exactly.
+fn point_row(anchor: (Row, f64), beat_length: f64, time_ms: f64) -> Row {
+    let rows = anchor.0.as_f64() + (time_ms - anchor.1) / beat_length * ROWS_PER_BEAT;
+    Snap::ALL
+        .iter()
+        .map(|snap| {
+            let spacing = snap.rows() as f64;
+            Row::from_rows(((rows / spacing).round() * spacing) as i64)
+        })
+        .find(|row| (time_at(anchor, beat_length, *row) - time_ms).abs() <= SNAP_TOLERANCE_MS)
+        .unwrap_or_else(|| {
+            let thousandths = (rows / ROWS_PER_BEAT * 1000.0).round() as i64;
+            Row::new(thousandths * ROWS_PER_BEAT as i64, 1000)
+        })
+}
+
+/// Row of each note time, and the times no snap reaches. Times are put on
+/// the coarsest snap within `SNAP_TOLERANCE_MS`; the others then go to the
+/// nearest 192nd no other time is on, so distinct notes are never merged.
+fn note_rows(beatmap: &OsuBeatmap, timing: &TimingData) -> (BTreeMap<i32, Row>, BTreeSet<i32>) {
+    let times: BTreeSet<i32> = beatmap
+        .hit_objects
+        .iter()
+        .flat_map(|object| match object.kind {
+            OsuHitObjectKind::Hold { end_time } => vec![object.time, end_time],
+            _ => vec![object.time],
+        })
+        .collect();
+
+    let mut rows = BTreeMap::new();
+    let mut off_snap = BTreeSet::new();
+    for &time in &times {
+        match snap_row(timing, time as f64) {
+            Some(row) => {
+                rows.insert(time, row);
+            }
+            None => {
+                off_snap.insert(time);
+            }
+        }
+    }
+
+    let mut taken: BTreeSet<Row> = rows.values().copied().collect();
+    for &time in &off_snap {
+        let exact = timing.row_at_time(time as f64);
+        let nearest = exact.round() as i64;
+        let toward = if exact >= nearest as f64 { 1 } else { -1 };
+        // Nearest first: then one row toward `exact`, one away, and so on
+        let row = std::iter::once(nearest)
+            .chain((1..).flat_map(|step: i64| [nearest + step * toward, nearest - step * toward]))
+            .map(Row::from_rows)
+            .find(|row| *row >= Row::ZERO && !taken.contains(row))
+            .unwrap();
+        taken.insert(row);
+        rows.insert(time, row);
+    }
+    (rows, off_snap)
+}
+
+/// Row of the coarsest snap within `SNAP_TOLERANCE_MS` of `time_ms`.
+fn snap_row(timing: &TimingData, time_ms: f64) -> Option<Row> {
+    let exact = timing.row_at_time(time_ms);
+    Snap::ALL
+        .iter()
+        .map(|snap| {
+            let spacing = snap.rows() as f64;
+            Row::from_rows(((exact / spacing).round() * spacing) as i64)
+        })
+        .find(|row| (timing.time_at_row(*row) - time_ms).abs() <= SNAP_TOLERANCE_MS)
+}
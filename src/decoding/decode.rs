@@ -1,377 +1,618 @@
-use crate::structs::{Chart, Measure, Beat};
+use crate::structs::{Beat, Chart, Diagnostic, HoldNote, Measure, NoteType, RawTag, SpeedSegment, TimingSegments};
 use crate::structs::SmFile;
-use crate::utils::{parse_field, parse_pairs};
+use crate::error::Error;
+use crate::decoding::msd::{self, MsdParam, MsdTag};
+use crate::timing::{Row, TimingData};
+use crate::utils::{parse_list, parse_seconds};
 use std::path::PathBuf;
 
-// StepMania row system constants
-const ROWS_PER_BEAT: f64 = 48.0;  // 1 beat = 48 rows (for 4/4 time)
-const ROWS_PER_MEASURE: f64 = 192.0;  // 1 measure = 192 rows (4 beats * 48)
+// Number of columns for each StepMania stepstype
+const STEPSTYPE_COLUMNS: &[(&str, u32)] = &[
+    ("dance-single", 4),
+    ("dance-double", 8),
+    ("dance-couple", 8),
+    ("dance-solo", 6),
+    ("dance-threepanel", 3),
+    ("dance-routine", 8),
+    ("pump-single", 5),
+    ("pump-halfdouble", 6),
+    ("pump-double", 10),
+    ("pump-couple", 10),
+    ("pump-routine", 10),
+    ("kb7-single", 7),
+    ("ez2-single", 5),
+    ("ez2-double", 10),
+    ("ez2-real", 7),
+    ("para-single", 5),
+    ("ds3ddx-single", 8),
+    ("bm-single5", 6),
+    ("bm-double5", 12),
+    ("bm-single7", 8),
+    ("bm-double7", 16),
+    ("maniax-single", 4),
+    ("maniax-double", 8),
+    ("techno-single4", 4),
+    ("techno-single5", 5),
+    ("techno-single8", 8),
+    ("techno-double4", 8),
+    ("techno-double5", 10),
+    ("techno-double8", 16),
+    ("pnm-five", 5),
+    ("pnm-nine", 9),
+    ("kickbox-human", 4),
+    ("kickbox-quadarm", 4),
+    ("kickbox-insect", 6),
+    ("kickbox-arachnid", 8),
+    ("lights-cabinet", 8),
+];
+
+// Song tags read into `Metadata`
+const METADATA_TAGS: &[&str] = &[
+    "TITLE",
+    "SUBTITLE",
+    "ARTIST",
+    "TITLETRANSLIT",
+    "SUBTITLETRANSLIT",
+    "ARTISTTRANSLIT",
+    "GENRE",
+    "CREDIT",
+    "MUSIC",
+    "BANNER",
+    "BACKGROUND",
+    "LYRICSPATH",
+    "CDTITLE",
+    "SAMPLESTART",
+    "SAMPLELENGTH",
+    "SELECTABLE",
+];
+
+// Timing tags, read for the song and, in .ssc files, for split-timing charts
+pub(crate) const TIMING_TAGS: &[&str] = &[
+    "OFFSET",
+    "BPMS",
+    "STOPS",
+    "DELAYS",
+    "WARPS",
+    "SPEEDS",
+    "SCROLLS",
+    "FAKES",
+    "TICKCOUNTS",
+    "COMBOS",
+    "LABELS",
+    "TIMESIGNATURES",
+];
 
 impl SmFile {
-    pub fn from_file(path: PathBuf) -> Result<SmFile, String> {
-        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
-        SmFile::parse(&content)
+    /// Reads a .sm file, or a .ssc file if the path has that extension.
+    pub fn from_file(path: PathBuf) -> Result<SmFile, Error> {
+        let content = std::fs::read_to_string(&path)?;
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ssc")) {
+            SmFile::from_ssc_string(&content)
+        } else {
+            SmFile::parse(&content)
+        }
     }
 
-    pub fn from_string(content: &str) -> Result<SmFile, String> {
+    pub fn from_string(content: &str) -> Result<SmFile, Error> {
         SmFile::parse(content)
     }
 
-    fn parse(content: &str) -> Result<SmFile, String> {
+    fn parse(content: &str) -> Result<SmFile, Error> {
+        let tags = msd::tokenize(content);
         let mut sm = SmFile::new();
-        sm.metadata.parse(content);
-        sm.parse_bpms(content);
-        sm.parse_stops(content);
-        // Parse offset
-        parse_field(content, r"#OFFSET:([-\d.]+);", &mut sm.offset);
-        sm.offset = sm.offset.abs() * 1000.0;
-        sm.parse_charts(content).map_err(|e| e.to_string())?;
-        return Ok(sm);
+        sm.parse_song_tags(&tags)?;
+        sm.parse_charts(&tags)?;
+        sm.keep_song_tags(content, &tags);
+        Ok(sm)
     }
 
-    fn parse_bpms(&mut self, content: &str) {
-        // Parse BPM pairs from the file
-        // Format: #BPMS:beat1=bpm1,beat2=bpm2,...;
-        parse_pairs(content, r"(?s)#BPMS:(.*?);", &mut self.bpms);
+    /// Records the order of the song tags, and keeps those that
+    /// `parse_song_tags` does not read as written.
+    pub(crate) fn keep_song_tags(&mut self, content: &str, tags: &[MsdTag]) {
+        let tags: Vec<&MsdTag> = tags.iter().filter(|tag| !tag.is("NOTES")).collect();
+        self.tag_order = tags.iter().map(|tag| tag.name.to_uppercase()).collect();
+
+        let known = |tag: &MsdTag| METADATA_TAGS.iter().chain(TIMING_TAGS).any(|name| tag.is(name));
+        self.other_tags = tags
+            .into_iter()
+            .filter(|tag| !known(tag))
+            .map(|tag| RawTag {
+                name: tag.name.clone(),
+                text: tag.source(content).to_string(),
+            })
+            .collect();
+    }
 
-        // Convert beats to rows (1 beat = 48 rows in StepMania)
-        // Store as (row, bpm) instead of (beat, bpm)
-        for (beat, _bpm) in &mut self.bpms {
-            *beat = *beat * ROWS_PER_BEAT;
+    /// Reads the song-level metadata and timing tags shared by .sm and .ssc.
+    pub(crate) fn parse_song_tags(&mut self, tags: &[MsdTag]) -> Result<(), Error> {
+        self.metadata.parse(tags)?;
+        if !parse_bpms(tags, &mut self.bpms)? {
+            return Err(Error::MissingTag { tag: "BPMS".to_string() });
         }
+        parse_stops(tags, &mut self.stops)?;
+        self.segments.parse(tags)?;
+        parse_offset(tags, &mut self.offset)?;
+        Ok(())
+    }
 
-        // Sort by row (first element of tuple)
-        self.bpms
-            .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    fn parse_charts(&mut self, tags: &[MsdTag]) -> Result<(), Error> {
+        let notes_tags = tags.iter().filter(|tag| tag.is("NOTES"));
 
-        // Ensure we have at least one BPM change at row 0
-        if self.bpms.is_empty() || self.bpms[0].0 > 0.0 {
-            self.bpms.insert(0, (0.0, 120.0));
+        let timing = TimingData::from_sm(self);
+        for (chart_idx, tag) in notes_tags.enumerate() {
+            let chart = Chart::parse(tag, chart_idx, &timing)?;
+            self.charts.push(chart);
         }
+        Ok(())
+    }
+}
+
+/// Parses #OFFSET into milliseconds, keeping its sign: beat 0 plays at
+/// -OFFSET seconds.
+pub(crate) fn parse_offset(tags: &[MsdTag], offset: &mut f64) -> Result<(), Error> {
+    parse_seconds(tags, "OFFSET", offset)
+}
+
+/// Replaces `bpms` with the #BPMS pairs if the tag is present. A zero BPM
+/// would never reach the next row and is a malformed entry.
+pub(crate) fn parse_bpms(tags: &[MsdTag], bpms: &mut Vec<(Row, f64)>) -> Result<bool, Error> {
+    // Parse BPM pairs from the file
+    // Format: #BPMS:beat1=bpm1,beat2=bpm2,...;
+    let bpm = |text: &str| parse_number(text).filter(|bpm| *bpm != 0.0 && bpm.is_finite());
+    let Some(mut parsed) = parse_row_pairs(tags, "BPMS", bpm)? else {
+        return Ok(false);
+    };
+
+    // Ensure we have at least one BPM change at row 0
+    if parsed.is_empty() || parsed[0].0 > Row::ZERO {
+        parsed.insert(0, (Row::ZERO, 120.0));
     }
+    *bpms = parsed;
+    Ok(true)
+}
 
-    fn parse_stops(&mut self, content: &str) {
-        // Parse stop pairs from the file
-        // Format: #STOPS:beat1=duration1,beat2=duration2,...;
-        parse_pairs(content, r"(?s)#STOPS:(.*?);", &mut self.stops);
+/// Replaces `stops` with the #STOPS pairs if the tag is present.
+pub(crate) fn parse_stops(tags: &[MsdTag], stops: &mut Vec<(Row, f64)>) -> Result<bool, Error> {
+    // Parse stop pairs from the file
+    // Format: #STOPS:beat1=duration1,beat2=duration2,...;
+    let Some(parsed) = parse_row_pairs(tags, "STOPS", parse_number)? else {
+        return Ok(false);
+    };
+    *stops = parsed;
+    Ok(true)
+}
+
+/// Parses `#TAG:beat=value,...;` into (row, value) pairs sorted by row.
+/// Beats are converted to exact rows (1 beat = 48 rows in StepMania).
+fn parse_row_pairs<T>(
+    tags: &[MsdTag],
+    tag: &str,
+    value: impl Fn(&str) -> Option<T>,
+) -> Result<Option<Vec<(Row, T)>>, Error> {
+    let Some(mut pairs) = parse_list(tags, tag, |fields| match fields {
+        [beat, text] => Some((Row::parse_beat(beat)?, value(text)?)),
+        _ => None,
+    })?
+    else {
+        return Ok(None);
+    };
+
+    // Sort by row (first element of tuple)
+    sort_by_row(&mut pairs, |pair| pair.0);
+    Ok(Some(pairs))
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    text.parse().ok()
+}
 
-        // Convert beats to rows (1 beat = 48 rows in StepMania)
-        // Store as (row, duration) instead of (beat, duration)
-        for (beat, _duration) in &mut self.stops {
-            *beat = *beat * ROWS_PER_BEAT;
+fn sort_by_row<T>(list: &mut [T], row: impl Fn(&T) -> Row) {
+    list.sort_by_key(row);
+}
+
+impl TimingSegments {
+    /// Replaces each segment list whose tag is present in `tags`.
+    pub(crate) fn parse(&mut self, tags: &[MsdTag]) -> Result<(), Error> {
+        // Format: #DELAYS:beat=seconds,...;
+        if let Some(delays) = parse_row_pairs(tags, "DELAYS", parse_number)? {
+            self.delays = delays;
         }
 
-        // Sort by row (first element of tuple)
-        self.stops
-            .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
-    }
+        // Format: #WARPS:beat=length in beats,...;
+        if let Some(warps) = parse_row_pairs(tags, "WARPS", Row::parse_beat)? {
+            self.warps = warps;
+        }
 
-    fn parse_charts(&mut self, content: &str) -> Result<(), String> {
-        let notes_sections: Vec<&str> = content.split("#NOTES:").skip(1).collect();
+        // Format: #SPEEDS:beat=ratio=duration=unit,...; (unit 1 = seconds)
+        if let Some(mut speeds) = parse_list(tags, "SPEEDS", |fields| {
+            let (beat, ratio, duration, unit) = match fields {
+                [beat, ratio, duration, unit] => (beat, ratio, duration, *unit),
+                [beat, ratio, duration] => (beat, ratio, duration, "0"),
+                _ => return None,
+            };
+            Some(SpeedSegment {
+                row: Row::parse_beat(beat)?,
+                ratio: ratio.parse().ok()?,
+                duration: duration.parse().ok()?,
+                in_seconds: unit.parse::<f64>().ok()? == 1.0,
+            })
+        })? {
+            sort_by_row(&mut speeds, |speed| speed.row);
+            self.speeds = speeds;
+        }
 
-        for notes_section in notes_sections {
-            // Find end of section (next #NOTES: or end)
-            let section_end = notes_section.find("#NOTES:").unwrap_or(notes_section.len());
-            let section_content = &notes_section[..section_end];
+        // Format: #SCROLLS:beat=ratio,...;
+        if let Some(scrolls) = parse_row_pairs(tags, "SCROLLS", parse_number)? {
+            self.scrolls = scrolls;
+        }
 
-            let chart = Chart::parse(section_content, &self.bpms).map_err(|e| e.to_string())?;
-            self.charts.push(chart);
+        // Format: #FAKES:beat=length in beats,...;
+        if let Some(fakes) = parse_row_pairs(tags, "FAKES", Row::parse_beat)? {
+            self.fakes = fakes;
+        }
+
+        // Format: #TICKCOUNTS:beat=ticks,...;
+        if let Some(tick_counts) = parse_row_pairs(tags, "TICKCOUNTS", |ticks| ticks.parse().ok())? {
+            self.tick_counts = tick_counts;
+        }
+
+        // Format: #COMBOS:beat=hit combo[=miss combo],...;
+        if let Some(mut combos) = parse_list(tags, "COMBOS", |fields| {
+            let (beat, hit, miss) = match fields {
+                [beat, hit, miss] => (beat, hit, miss),
+                [beat, hit] => (beat, hit, hit),
+                _ => return None,
+            };
+            Some((Row::parse_beat(beat)?, hit.parse().ok()?, miss.parse().ok()?))
+        })? {
+            sort_by_row(&mut combos, |combo| combo.0);
+            self.combos = combos;
+        }
+
+        // Format: #LABELS:beat=text,...;
+        if let Some(labels) = parse_row_pairs(tags, "LABELS", |label| Some(label.to_string()))? {
+            self.labels = labels;
+        }
+
+        // Format: #TIMESIGNATURES:beat=numerator=denominator,...;
+        if let Some(mut signatures) = parse_list(tags, "TIMESIGNATURES", |fields| match fields {
+            [beat, num, den] => Some((Row::parse_beat(beat)?, num.parse().ok()?, den.parse().ok()?)),
+            _ => None,
+        })? {
+            sort_by_row(&mut signatures, |signature| signature.0);
+            self.time_signatures = signatures;
         }
+
         Ok(())
     }
 }
 
 impl Chart {
-    fn parse(content: &str, bpms: &[(f64, f64)]) -> Result<Chart, String> {
-        let lines: Vec<&str> = content.lines().map(|l| l.trim()).collect();
-
+    fn parse(tag: &MsdTag, chart_idx: usize, timing: &TimingData) -> Result<Chart, Error> {
         let mut chart = Chart::new();
     
         // Parse chart header
-        let mut idx = chart.parse_header(&lines);
+        chart.parse_header(tag, chart_idx)?;
+
+        // Note data is the sixth param, after the five header fields
+        chart.parse_note_data(&tag.params[5], chart_idx, timing)?;
     
-        // Parse measures
-        // Timing state - using row-based system
-        let mut current_bpm = if bpms.is_empty() { 120.0 } else { bpms[0].1 };
-        let mut current_time_ms = 0.0; // Time in MILLISECONDS
-        let mut current_row = 0.0; // Position in rows (not beats!)
-        let mut bpm_index = 0;
+        Ok(chart)
+    }
 
-        while idx < lines.len() {
-            // Parse next measure (it will handle BPM changes internally)
-            let (measure, next_idx, new_time_ms, new_row) = Measure::parse(
-                &lines, 
-                idx, 
-                bpms,
-                &mut current_bpm,
-                &mut bpm_index,
-                current_time_ms, 
-                current_row
-            );
+    /// Decodes the measures of a chart's note data and pairs its holds.
+    /// `column_count` must already be set.
+    pub(crate) fn parse_note_data(
+        &mut self,
+        notes: &MsdParam,
+        chart_idx: usize,
+        timing: &TimingData,
+    ) -> Result<(), Error> {
+        let mut start = 0;
+        let section = NotesSection {
+            notes,
+            lines: notes
+                .value
+                .split('\n')
+                .map(|line| {
+                    let entry = (start, line);
+                    start += line.len() + 1;
+                    entry
+                })
+                .collect(),
+            chart: chart_idx,
+        };
+        let mut idx = 0;
+    
+        // Parse measures
+        while idx < section.lines.len() {
+            // Parse next measure (timing converts its rows to times)
+            let (measure, next_idx) = Measure::parse(
+                &section,
+                idx,
+                self.measures.len(),
+                self.column_count,
+                timing,
+            )?;
             
             // Always add measure, even if empty (empty measures represent time)
-            chart.measures.push(measure);
+            self.measures.push(measure);
 
-            current_time_ms = new_time_ms;
-            current_row = new_row;
             idx = next_idx;
+        }
 
-            // Check if we hit a semicolon (end of chart)
-            if idx > 0 && idx <= lines.len() {
-                let prev_line = lines[idx - 1].trim();
-                let line_without_comment = if let Some(comment_pos) = prev_line.find("//") {
-                    &prev_line[..comment_pos]
-                } else {
-                    prev_line
-                }
-                .trim();
-                
-                if line_without_comment == ";" {
-                    break;
+        self.pair_holds();
+        Ok(())
+    }
+
+    /// Column count of a stepstype such as `dance-single` or `pump-double`,
+    /// or `None` if the stepstype is unknown.
+    pub fn column_count_for(stepstype: &str) -> Option<u32> {
+        let stepstype = stepstype.trim().to_lowercase();
+        STEPSTYPE_COLUMNS
+            .iter()
+            .find(|(name, _)| *name == stepstype)
+            .map(|(_, columns)| *columns)
+    }
+
+    /// Most common stepstype with `columns` columns (`dance-single` for 4,
+    /// `kb7-single` for 7), or `None` if StepMania has none.
+    pub fn stepstype_for(columns: u32) -> Option<&'static str> {
+        STEPSTYPE_COLUMNS
+            .iter()
+            .find(|(_, count)| *count == columns)
+            .map(|(name, _)| *name)
+    }
+
+    /// Pairs every hold/roll head with the next tail in its column, across
+    /// measures. Heads left open and tails with no head become diagnostics.
+    pub fn pair_holds(&mut self) {
+        self.holds.clear();
+        self.diagnostics.retain(|d| {
+            !matches!(d, Diagnostic::UnterminatedHold { .. } | Diagnostic::OrphanTail { .. })
+        });
+
+        // Open head per column: (start time, is_roll)
+        let mut open: Vec<Option<(f64, bool)>> = Vec::new();
+
+        for beat in self.measures.iter().flat_map(|m| m.beats.iter()) {
+            if open.len() < beat.notes.len() {
+                open.resize(beat.notes.len(), None);
+            }
+
+            for (column, note) in beat.notes.iter().enumerate() {
+                match note {
+                    NoteType::HoldHead | NoteType::RollHead => {
+                        // A new head while one is still open means the first never ended
+                        if let Some((start_ms, _)) = open[column] {
+                            self.diagnostics.push(Diagnostic::UnterminatedHold { column, start_ms });
+                        }
+                        open[column] = Some((beat.time, *note == NoteType::RollHead));
+                    }
+                    NoteType::Tail => match open[column].take() {
+                        Some((start_ms, is_roll)) => self.holds.push(HoldNote {
+                            column,
+                            start_ms,
+                            end_ms: beat.time,
+                            is_roll,
+                        }),
+                        None => self.diagnostics.push(Diagnostic::OrphanTail {
+                            column,
+                            time_ms: beat.time,
+                        }),
+                    },
+                    _ => {}
                 }
             }
+        }
 
-            // If we're at EOF, stop
-            if idx >= lines.len() {
-                break;
+        for (column, head) in open.into_iter().enumerate() {
+            if let Some((start_ms, _)) = head {
+                self.diagnostics.push(Diagnostic::UnterminatedHold { column, start_ms });
             }
         }
-    
-        Ok(chart)
     }
 
-    fn parse_header(&mut self, lines: &[&str]) -> usize {
-        let mut idx = 0;
-    
-        // Skip empty lines
-        while idx < lines.len() && lines[idx].is_empty() {
-            idx += 1;
+    fn parse_header(&mut self, tag: &MsdTag, chart_idx: usize) -> Result<(), Error> {
+        // The header is five fields: stepstype, description, difficulty,
+        // meter and radar values, followed by the note data
+        if tag.params.len() < 6 {
+            return Err(Error::MalformedValue {
+                tag: "NOTES".to_string(),
+                text: format!("{} of 6 fields", tag.params.len()),
+                line: tag.span.line,
+                column: tag.span.column,
+            });
         }
-    
-        // Stepstype
-        if idx < lines.len() {
-            self.stepstype = lines[idx].to_string();
-            idx += 1;
-        }
-    
-        // Skip description (empty or ":")
-        while idx < lines.len() && (lines[idx].is_empty() || lines[idx] == ":") {
-            idx += 1;
-        }
-    
-        // Difficulty
-        if idx < lines.len() {
-            self.difficulty = lines[idx].to_string();
-            idx += 1;
-        }
-    
-        // Skip empty lines
-        while idx < lines.len() && lines[idx].is_empty() {
-            idx += 1;
-        }
-    
+
+        let malformed = |param: &MsdParam, text: &str| {
+            let (line, column) = param.position_at(param.value.find(text).unwrap_or(0));
+            Error::MalformedValue {
+                tag: "NOTES".to_string(),
+                text: text.to_string(),
+                line,
+                column,
+            }
+        };
+
+        self.stepstype = tag.params[0].value.trim().to_string();
+        self.description = tag.params[1].value.trim().to_string();
+        self.difficulty = tag.params[2].value.trim().to_string();
+
         // Meter
-        if idx < lines.len() {
-            self.meter = lines[idx].parse().unwrap_or(0);
-            idx += 1;
+        let meter = tag.params[3].value.trim();
+        if !meter.is_empty() {
+            self.meter = meter.parse().map_err(|_| malformed(&tag.params[3], meter))?;
         }
-    
-        // Skip empty lines
-        while idx < lines.len() && lines[idx].is_empty() {
-            idx += 1;
-        }
-    
+
         // Radar values
-        if idx < lines.len() {
-            for val in lines[idx].split(',') {
-                if let Ok(v) = val.trim().parse::<f64>() {
-                    self.radar_values.push(v);
-                }
-            }
-            idx += 1;
+        let radar = &tag.params[4];
+        for val in radar.value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            let value = val.parse::<f64>().map_err(|_| malformed(radar, val))?;
+            self.radar_values.push(value);
         }
-    
-        idx
+
+        self.column_count = Chart::column_count_for(&self.stepstype).ok_or_else(|| {
+            let stepstype = &tag.params[0];
+            let leading = stepstype.value.len() - stepstype.value.trim_start().len();
+            Error::UnknownStepsType {
+                chart: chart_idx,
+                stepstype: self.stepstype.clone(),
+                line: stepstype.position_at(leading).0,
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Lines of a chart's note data, plus the parameter they come from so
+/// errors can point at absolute positions.
+struct NotesSection<'a> {
+    notes: &'a MsdParam,
+    lines: Vec<(usize, &'a str)>, // (byte offset in the note data, line)
+    chart: usize,
+}
+
+impl NotesSection<'_> {
+    /// 1-based (line, column) of byte `offset` of `text`, found within the
+    /// section line `idx`.
+    fn position(&self, idx: usize, text: &str, offset: usize) -> (usize, usize) {
+        let (start, line) = self.lines[idx];
+        self.notes.position_at(start + line.find(text).unwrap_or(0) + offset)
     }
 }
 
 impl Measure {
     fn parse(
-        lines: &[&str], 
-        start_idx: usize, 
-        bpms: &[(f64, f64)],  // (row, bpm) pairs
-        current_bpm: &mut f64,
-        bpm_index: &mut usize,
-        start_time_ms: f64, 
-        start_row: f64,
-    ) -> (Measure, usize, f64, f64) {
+        section: &NotesSection,
+        start_idx: usize,
+        measure_idx: usize,
+        column_count: u32,
+        timing: &TimingData,
+    ) -> Result<(Measure, usize), Error> {
+        let lines = &section.lines;
         let mut measure = Measure::new();
         let mut idx = start_idx;
 
         // Parse lines until we hit a comma or semicolon
         let mut note_lines = Vec::new();
         while idx < lines.len() {
-            let line = lines[idx].trim();
+            // Comments were already stripped by the MSD tokenizer
+            let line_without_comment = lines[idx].1.trim();
 
-            if line.is_empty() {
+            if line_without_comment.is_empty() {
                 idx += 1;
                 continue;
             }
 
-            // Remove comments from line
-            let line_without_comment = if let Some(comment_pos) = line.find("//") {
-                &line[..comment_pos]
-            } else {
-                line
+            // Check if line is a measure separator
+            if line_without_comment == "," {
+                break;
             }
-            .trim();
 
-            // Check if line is a measure separator (comma or semicolon)
-            if line_without_comment == "," || line_without_comment == ";" {
-                break;
-            } else if Beat::is_note_line(line_without_comment) {
-                // Store note lines for later processing
-                note_lines.push(line_without_comment);
+            let (line, _) = section.position(idx, line_without_comment, 0);
+            let (notes, bad_char) = note_columns(line_without_comment);
+            if let Some(bad_char) = bad_char {
+                let (line, column) = section.position(idx, line_without_comment, bad_char);
+                return Err(Error::BadNoteLine {
+                    chart: section.chart,
+                    measure: measure_idx,
+                    text: line_without_comment.to_string(),
+                    line,
+                    column,
+                });
+            }
+            if notes.len() != column_count as usize {
+                return Err(Error::ColumnCountMismatch {
+                    chart: section.chart,
+                    measure: measure_idx,
+                    expected: column_count,
+                    found: notes.len(),
+                    line,
+                });
             }
 
+            // Store note lines for later processing
+            note_lines.push(line_without_comment);
             idx += 1;
         }
 
-        // Calculate quantization (rows per note line)
-        // A measure has 192 rows total
+        // Note lines split the measure's 192 rows evenly; rows are kept as
+        // exact fractions, so any line count (e.g. 7 per measure) is lossless
         let num_lines = note_lines.len();
-        let quantization = if num_lines > 0 {
-            // Determine quantization by checking if measure uses full 192 rows
-            if num_lines == ROWS_PER_MEASURE as usize {
-                // Full 192-row measure - check for custom quantization
-                // Try to find the smallest valid quantization
-                let mut found_quant = ROWS_PER_MEASURE as usize;
-                for test_quant in [4, 8, 12, 16, 24, 32, 48, 64, 96] {
-                    if ROWS_PER_MEASURE as usize % test_quant == 0 {
-                        // For now, accept the first valid quantization
-                        // A more sophisticated check would verify that compressed rows are empty
-                        found_quant = test_quant;
-                        break;
-                    }
-                }
-                found_quant
-            } else if num_lines > 0 {
-                // Calculate quantization from number of lines
-                ROWS_PER_MEASURE as usize / num_lines
-            } else {
-                ROWS_PER_MEASURE as usize
-            }
-        } else {
-            ROWS_PER_MEASURE as usize
-        };
-
-        // Process note lines and calculate timings
-        measure.start_time = start_time_ms;
-        let mut current_time = start_time_ms;
-        let mut current_row = start_row;
+        measure.start_time = timing.time_at_row(Row::in_measure(measure_idx, 0, 1));
 
         for (line_idx, line) in note_lines.iter().enumerate() {
-            // Calculate row position for this note line
-            let row_offset = if num_lines > 0 {
-                // Handle quantization
-                if ROWS_PER_MEASURE as usize % num_lines == 0 {
-                    (line_idx * quantization) as f64
-                } else {
-                    // Non-uniform spacing
-                    (ROWS_PER_MEASURE as f64 / num_lines as f64) * line_idx as f64
-                }
-            } else {
-                0.0
-            };
-
-            let note_row = start_row + row_offset;
-
-            // Check for BPM changes up to this row
-            while *bpm_index < bpms.len() {
-                let (bpm_row, new_bpm) = bpms[*bpm_index];
-                if bpm_row <= note_row {
-                    // Calculate time elapsed from previous position to this BPM change
-                    if bpm_row > current_row {
-                        let rows_elapsed = bpm_row - current_row;
-                        let beats_elapsed = rows_elapsed / ROWS_PER_BEAT;
-                        let time_elapsed_ms = (beats_elapsed / *current_bpm) * 60000.0;
-                        current_time += time_elapsed_ms;
-                        current_row = bpm_row;
-                    }
-                    *current_bpm = new_bpm;
-                    *bpm_index += 1;
-                } else {
-                    break;
-                }
-            }
-
-            // Calculate time for this note line
-            if note_row > current_row {
-                let rows_elapsed = note_row - current_row;
-                let beats_elapsed = rows_elapsed / ROWS_PER_BEAT;
-                let time_elapsed_ms = (beats_elapsed / *current_bpm) * 60000.0;
-                current_time += time_elapsed_ms;
-                current_row = note_row;
-            }
+            let note_row = Row::in_measure(measure_idx, line_idx, num_lines);
 
             // Parse and store the beat
             let mut beat = Beat::parse(line);
-            beat.time = current_time;
+            beat.row = note_row;
+            beat.time = timing.time_at_row(note_row);
+            beat.judged = timing.is_judged_at_row(note_row);
             measure.beats.push(beat);
         }
 
-        // Calculate final row and time for the end of the measure
-        let end_row = start_row + ROWS_PER_MEASURE;
-        
-        // Check for any remaining BPM changes before end of measure
-        while *bpm_index < bpms.len() {
-            let (bpm_row, new_bpm) = bpms[*bpm_index];
-            if bpm_row < end_row {
-                // Calculate time elapsed to this BPM change
-                if bpm_row > current_row {
-                    let rows_elapsed = bpm_row - current_row;
-                    let beats_elapsed = rows_elapsed / ROWS_PER_BEAT;
-                    let time_elapsed_ms = (beats_elapsed / *current_bpm) * 60000.0;
-                    current_time += time_elapsed_ms;
-                    current_row = bpm_row;
-                }
-                *current_bpm = new_bpm;
-                *bpm_index += 1;
-            } else {
-                break;
-            }
-        }
-
-        // Calculate time from current position to end of measure
-        if end_row > current_row {
-            let rows_elapsed = end_row - current_row;
-            let beats_elapsed = rows_elapsed / ROWS_PER_BEAT;
-            let time_elapsed_ms = (beats_elapsed / *current_bpm) * 60000.0;
-            current_time += time_elapsed_ms;
-        }
-
-        let next_idx = if idx < lines.len() && (lines[idx].trim() == "," || lines[idx].trim() == ";") {
+        // The scan above only stops early on a separator
+        let next_idx = if idx < lines.len() {
             idx + 1
         } else {
             idx
         };
 
-        (measure, next_idx, current_time, end_row)
+        Ok((measure, next_idx))
     }
 }
 
 impl Beat {
     pub fn is_note_line(line: &str) -> bool {
-        line.chars()
-            .all(|c| matches!(c, '0' | '1' | '2' | '3' | '4' | 'M'))
+        let (notes, bad_char) = note_columns(line);
+        !notes.is_empty() && bad_char.is_none()
     }
 
+    /// Reads one note per column. StepMania 5 keysound indices (`1[2]`) and
+    /// attacks (`1{...}`) after a note are skipped; invalid notes are empty.
     pub fn parse(line: &str) -> Beat {
-        let notes = line.chars().map(|c| c != '0').collect();
+        let (notes, _) = note_columns(line);
         Beat {
+            row: Row::ZERO, // Set by Measure::parse
             time: 0.0, // Will be calculated when measure ends
             notes,
+            judged: true,
         }
     }
 }
 
+/// The note of each column of `line`, skipping the `[keysound]` and
+/// `{attack}` groups StepMania 5 writes after a note, and the byte offset of
+/// the first character that is neither a note nor such a group, if any.
+/// Invalid notes are read as empty.
+fn note_columns(line: &str) -> (Vec<NoteType>, Option<usize>) {
+    let mut notes = Vec::with_capacity(line.len());
+    let mut bad_char = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let closing = match c {
+            '[' => Some(']'),
+            '{' => Some('}'),
+            _ => None,
+        };
+        match closing {
+            // A group only follows a note, and must be closed
+            Some(closing) if !notes.is_empty() => {
+                if !chars.by_ref().any(|(_, c)| c == closing) {
+                    bad_char = bad_char.or(Some(offset));
+                }
+            }
+            _ => {
+                let note = NoteType::from_char(c);
+                if note.is_none() {
+                    bad_char = bad_char.or(Some(offset));
+                }
+                notes.push(note.unwrap_or(NoteType::Empty));
+            }
+        }
+    }
+    (notes, bad_char)
+}
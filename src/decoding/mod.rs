@@ -1 +1,4 @@
 pub mod decode;
+pub mod msd;
+pub mod osu;
+pub mod ssc;
@@ -0,0 +1,225 @@
+// MSD is the `#TAG:param:param;` container format shared by .sm, .ssc and .dwi files.
+
+/// Where a piece of text starts in the source file. `offset` is a byte index,
+/// `line` and `column` are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub offset: usize,
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MsdParam {
+    pub value: String, // Unescaped, with `//` comments removed
+    pub span: Span,
+    pub escapes: Vec<usize>, // Byte offsets in `value` of the characters written after a `\`
+}
+
+impl MsdParam {
+    /// 1-based (line, column) in the source of a byte offset inside `value`,
+    /// counting the `\` of each escape up to it (an escaped character is
+    /// located after its `\`).
+    pub fn position_at(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.value.len());
+        let before = &self.value[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let escapes = self
+            .escapes
+            .iter()
+            .filter(|&&escape| (line_start..=offset).contains(&escape))
+            .count();
+        let column = before[line_start..].chars().count() + escapes;
+        if line_start == 0 {
+            (self.span.line, self.span.column + column)
+        } else {
+            (self.span.line + before.matches('\n').count(), column + 1)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MsdTag {
+    pub name: String, // As written, without the leading '#'
+    pub params: Vec<MsdParam>,
+    pub span: Span, // From '#' up to and including ';'
+}
+
+impl MsdTag {
+    /// Tag names are case-insensitive in StepMania.
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    /// First parameter, trimmed; empty if the tag has none.
+    pub fn value(&self) -> &str {
+        self.params.first().map_or("", |p| p.value.trim())
+    }
+
+    /// The tag as written in `content`, the text it was tokenized from.
+    pub fn source<'a>(&self, content: &'a str) -> &'a str {
+        &content[self.span.offset..self.span.offset + self.span.len]
+    }
+}
+
+/// First tag named `name` (case-insensitive).
+pub fn find_tag<'a>(tags: &'a [MsdTag], name: &str) -> Option<&'a MsdTag> {
+    tags.iter().find(|tag| tag.is(name))
+}
+
+/// Splits MSD text into tags in a single pass.
+///
+/// Follows StepMania's reader: `//` comments run to the end of the line,
+/// `\` escapes the next character, `:` separates parameters and `;` ends a
+/// tag. A `#` at the start of a line also ends a tag missing its `;`.
+pub fn tokenize(content: &str) -> Vec<MsdTag> {
+    let mut tokenizer = Tokenizer {
+        content,
+        tags: Vec::new(),
+        current: None,
+        line: 1,
+        column: 1,
+        line_blank: true,
+    };
+    tokenizer.run();
+    tokenizer.tags
+}
+
+struct Tokenizer<'a> {
+    content: &'a str,
+    tags: Vec<MsdTag>,
+    current: Option<(Span, Vec<MsdParam>)>, // Tag being read: its start and params so far
+    line: usize,
+    column: usize,
+    line_blank: bool, // Only whitespace seen so far on the current line
+}
+
+impl Tokenizer<'_> {
+    fn run(&mut self) {
+        let mut chars = self.content.char_indices().peekable();
+
+        while let Some((offset, c)) = chars.next() {
+            let next = chars.peek().map(|&(_, next)| next);
+
+            if c == '/' && next == Some('/') {
+                // Comment: skip to (but not past) the end of the line
+                while let Some(&(_, skipped)) = chars.peek() {
+                    if skipped == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+                continue;
+            }
+
+            if c == '#' && (self.current.is_none() || self.line_blank) {
+                // A '#' opening a line closes a tag that never got its ';'
+                self.finish_tag(offset, offset);
+                let span = Span {
+                    offset,
+                    len: 0,
+                    line: self.line,
+                    column: self.column,
+                };
+                self.current = Some((span, Vec::new()));
+                self.start_param(offset + 1);
+                self.advance(c);
+                continue;
+            }
+
+            if self.current.is_some() {
+                match c {
+                    '\\' if next.is_some() => {
+                        let (_, escaped) = chars.next().unwrap();
+                        self.push_escaped(escaped);
+                        self.advance(c);
+                        self.advance(escaped);
+                        continue;
+                    }
+                    ':' => {
+                        self.end_param(offset);
+                        self.start_param(offset + 1);
+                    }
+                    ';' => {
+                        self.finish_tag(offset, offset + 1);
+                        self.advance(c);
+                        continue;
+                    }
+                    _ => self.push_char(c),
+                }
+            }
+            self.advance(c);
+        }
+
+        self.finish_tag(self.content.len(), self.content.len());
+    }
+
+    fn advance(&mut self, c: char) {
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+            self.line_blank = true;
+        } else {
+            self.column += 1;
+            if !c.is_whitespace() {
+                self.line_blank = false;
+            }
+        }
+    }
+
+    /// Opens a parameter right after the '#' or ':' at the current column.
+    fn start_param(&mut self, offset: usize) {
+        let span = Span {
+            offset,
+            len: 0,
+            line: self.line,
+            column: self.column + 1,
+        };
+        if let Some((_, params)) = &mut self.current {
+            params.push(MsdParam {
+                value: String::new(),
+                span,
+                escapes: Vec::new(),
+            });
+        }
+    }
+
+    fn end_param(&mut self, end: usize) {
+        if let Some((_, params)) = &mut self.current
+            && let Some(param) = params.last_mut()
+        {
+            param.span.len = end - param.span.offset;
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        if let Some((_, params)) = &mut self.current
+            && let Some(param) = params.last_mut()
+        {
+            param.value.push(c);
+        }
+    }
+
+    fn push_escaped(&mut self, c: char) {
+        if let Some((_, params)) = &mut self.current
+            && let Some(param) = params.last_mut()
+        {
+            param.escapes.push(param.value.len());
+            param.value.push(c);
+        }
+    }
+
+    /// Closes the open tag, if any. `param_end` is where its last parameter
+    /// stops and `end` the byte just past the tag (after its ';', if any).
+    fn finish_tag(&mut self, param_end: usize, end: usize) {
+        self.end_param(param_end);
+        let Some((mut span, mut params)) = self.current.take() else {
+            return;
+        };
+        span.len = end - span.offset;
+
+        let name = params.remove(0).value.trim().to_string();
+        self.tags.push(MsdTag { name, params, span });
+    }
+}
@@ -0,0 +1,291 @@
+use crate::error::Error;
+use crate::structs::{
+    OsuBeatmap, OsuDifficulty, OsuEditor, OsuEvent, OsuGeneral, OsuHitObject, OsuHitObjectKind,
+    OsuMetadata, OsuTimingPoint,
+};
+use crate::utils::log_debug;
+use std::path::PathBuf;
+
+impl OsuBeatmap {
+    pub fn from_file(path: PathBuf) -> Result<OsuBeatmap, Error> {
+        let content = std::fs::read_to_string(&path)?;
+        OsuBeatmap::from_string(&content)
+    }
+
+    /// Reads .osu text: the "osu file format vN" header, then `[Section]`
+    /// blocks in any order. `//` comment lines and unknown sections are skipped.
+    pub fn from_string(content: &str) -> Result<OsuBeatmap, Error> {
+        let mut beatmap = OsuBeatmap::new();
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim_end()))
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        // Format: osu file format v14 (files often start with a BOM)
+        let (header_line, header) = lines.next().unwrap_or((1, ""));
+        let header = header.trim().trim_start_matches('\u{feff}');
+        beatmap.format_version = header
+            .strip_prefix("osu file format v")
+            .and_then(|version| version.trim().parse().ok())
+            .ok_or_else(|| Error::MalformedOsuLine {
+                section: String::new(),
+                text: header.to_string(),
+                line: header_line,
+            })?;
+
+        let mut section = String::new();
+        let mut approach_rate_set = false;
+        for (line_number, raw) in lines {
+            let line = raw.trim();
+            if line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_string();
+                continue;
+            }
+
+            let parsed = match section.as_str() {
+                "General" => key_value(line).and_then(|(key, value)| beatmap.general.read(key, value)),
+                "Editor" => key_value(line).and_then(|(key, value)| beatmap.editor.read(key, value)),
+                "Metadata" => key_value(line).and_then(|(key, value)| beatmap.metadata.read(key, value)),
+                "Difficulty" => key_value(line).and_then(|(key, value)| {
+                    approach_rate_set |= key == "ApproachRate";
+                    beatmap.difficulty.read(key, value)
+                }),
+                // Storyboard lines keep their leading spaces/underscores, which nest commands
+                "Events" => OsuEvent::parse(raw).map(|event| beatmap.events.push(event)),
+                "TimingPoints" => OsuTimingPoint::parse(line).map(|point| beatmap.timing_points.push(point)),
+                "Colours" => parse_colour(line).map(|colour| beatmap.colours.push(colour)),
+                "HitObjects" => OsuHitObject::parse(line).map(|object| beatmap.hit_objects.push(object)),
+                _ => {
+                    log_debug!("[OsuBeatmap::from_string] Skipping line in [{}]: '{}'", section, line);
+                    Some(())
+                }
+            };
+            if parsed.is_none() {
+                return Err(Error::MalformedOsuLine {
+                    section,
+                    text: line.to_string(),
+                    line: line_number,
+                });
+            }
+        }
+
+        // Old maps have no ApproachRate and use the overall difficulty
+        if !approach_rate_set {
+            beatmap.difficulty.approach_rate = beatmap.difficulty.overall_difficulty;
+        }
+        Ok(beatmap)
+    }
+}
+
+/// Splits `Key: value` (or `Key:value`) into trimmed parts.
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    line.split_once(':').map(|(key, value)| (key.trim(), value.trim()))
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+/// Integer field that some editors write with decimals.
+fn parse_int(value: &str) -> Option<i32> {
+    value
+        .parse::<i32>()
+        .ok()
+        .or_else(|| value.parse::<f64>().ok().map(|value| value.round() as i32))
+}
+
+impl OsuGeneral {
+    fn read(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            "AudioFilename" => self.audio_filename = value.to_string(),
+            "AudioLeadIn" => self.audio_lead_in = parse_int(value)?,
+            "PreviewTime" => self.preview_time = parse_int(value)?,
+            "Countdown" => self.countdown = value.parse().ok()?,
+            "SampleSet" => self.sample_set = value.to_string(),
+            "StackLeniency" => self.stack_leniency = value.parse().ok()?,
+            "Mode" => self.mode = value.parse().ok()?,
+            "LetterboxInBreaks" => self.letterbox_in_breaks = parse_flag(value)?,
+            "SpecialStyle" => self.special_style = parse_flag(value)?,
+            "WidescreenStoryboard" => self.widescreen_storyboard = parse_flag(value)?,
+            _ => self.other.push((key.to_string(), value.to_string())),
+        }
+        Some(())
+    }
+}
+
+impl OsuEditor {
+    fn read(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            // Format: Bookmarks: 1000,2000,...
+            "Bookmarks" => {
+                self.bookmarks = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|time| !time.is_empty())
+                    .map(parse_int)
+                    .collect::<Option<_>>()?
+            }
+            "DistanceSpacing" => self.distance_spacing = value.parse().ok()?,
+            "BeatDivisor" => self.beat_divisor = value.parse().ok()?,
+            "GridSize" => self.grid_size = value.parse().ok()?,
+            "TimelineZoom" => self.timeline_zoom = value.parse().ok()?,
+            _ => self.other.push((key.to_string(), value.to_string())),
+        }
+        Some(())
+    }
+}
+
+impl OsuMetadata {
+    fn read(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            "Title" => self.title = value.to_string(),
+            "TitleUnicode" => self.title_unicode = value.to_string(),
+            "Artist" => self.artist = value.to_string(),
+            "ArtistUnicode" => self.artist_unicode = value.to_string(),
+            "Creator" => self.creator = value.to_string(),
+            "Version" => self.version = value.to_string(),
+            "Source" => self.source = value.to_string(),
+            "Tags" => self.tags = value.split_whitespace().map(str::to_string).collect(),
+            "BeatmapID" => self.beatmap_id = value.parse().ok()?,
+            "BeatmapSetID" => self.beatmap_set_id = value.parse().ok()?,
+            _ => self.other.push((key.to_string(), value.to_string())),
+        }
+        Some(())
+    }
+}
+
+impl OsuDifficulty {
+    fn read(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            "HPDrainRate" => self.hp_drain_rate = value.parse().ok()?,
+            "CircleSize" => self.circle_size = value.parse().ok()?,
+            "OverallDifficulty" => self.overall_difficulty = value.parse().ok()?,
+            "ApproachRate" => self.approach_rate = value.parse().ok()?,
+            "SliderMultiplier" => self.slider_multiplier = value.parse().ok()?,
+            "SliderTickRate" => self.slider_tick_rate = value.parse().ok()?,
+            _ => self.other.push((key.to_string(), value.to_string())),
+        }
+        Some(())
+    }
+}
+
+impl OsuEvent {
+    /// Format: type,start time,params... (types may be numbers or names)
+    fn parse(line: &str) -> Option<OsuEvent> {
+        let fields: Vec<&str> = line.trim().split(',').map(str::trim).collect();
+        let filename = |idx: usize| fields.get(idx).map(|name| name.trim_matches('"').to_string());
+        let offset = |idx: usize| fields.get(idx).map_or(Some(0), |value| parse_int(value));
+
+        // Only unindented lines are events; indented ones are storyboard commands
+        if line.starts_with([' ', '_']) {
+            return Some(OsuEvent::Other(line.to_string()));
+        }
+        let event = match fields[0] {
+            // Format: 0,0,"filename",x,y
+            "0" | "Background" => OsuEvent::Background {
+                filename: filename(2)?,
+                x: offset(3)?,
+                y: offset(4)?,
+            },
+            // Format: Video,start time,"filename",x,y
+            "1" | "Video" => OsuEvent::Video {
+                start_time: parse_int(fields.get(1)?)?,
+                filename: filename(2)?,
+                x: offset(3)?,
+                y: offset(4)?,
+            },
+            // Format: 2,start time,end time
+            "2" | "Break" => OsuEvent::Break {
+                start_time: parse_int(fields.get(1)?)?,
+                end_time: parse_int(fields.get(2)?)?,
+            },
+            _ => OsuEvent::Other(line.to_string()),
+        };
+        Some(event)
+    }
+}
+
+impl OsuTimingPoint {
+    /// Format: time,beatLength,meter,sampleSet,sampleIndex,volume,uninherited,effects
+    /// Fields after beatLength are missing from old maps and take their defaults.
+    fn parse(line: &str) -> Option<OsuTimingPoint> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let field = |idx: usize, default: u32| fields.get(idx).map_or(Some(default), |v| v.parse().ok());
+        Some(OsuTimingPoint {
+            time: fields.first()?.parse().ok()?,
+            beat_length: fields.get(1)?.parse().ok()?,
+            meter: field(2, 4)?,
+            sample_set: field(3, 0)?,
+            sample_index: field(4, 0)?,
+            volume: field(5, 100)?,
+            uninherited: field(6, 1)? == 1,
+            effects: field(7, 0)?,
+        })
+    }
+}
+
+/// Format: Combo1 : 255,128,0
+fn parse_colour(line: &str) -> Option<(String, [u8; 3])> {
+    let (key, value) = key_value(line)?;
+    let channels: Vec<u8> = value
+        .split(',')
+        .map(|channel| channel.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    Some((key.to_string(), [*channels.first()?, *channels.get(1)?, *channels.get(2)?]))
+}
+
+// Hit object type bits
+const TYPE_SLIDER: u32 = 2;
+const TYPE_SPINNER: u32 = 8;
+const TYPE_HOLD: u32 = 128;
+
+impl OsuHitObject {
+    /// Format: x,y,time,type,hitSound,objectParams...,hitSample
+    fn parse(line: &str) -> Option<OsuHitObject> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < 5 {
+            return None;
+        }
+        let object_type: u32 = fields[3].parse().ok()?;
+        let sample = |idx: usize| fields.get(idx).map_or(String::new(), |s| s.to_string());
+
+        let (kind, hit_sample) = if object_type & TYPE_HOLD != 0 {
+            // Format: ...,endTime:hitSample
+            let (end_time, hit_sample) = fields.get(5)?.split_once(':').unwrap_or((fields[5], ""));
+            let kind = OsuHitObjectKind::Hold {
+                end_time: parse_int(end_time)?,
+            };
+            (kind, hit_sample.to_string())
+        } else if object_type & TYPE_SPINNER != 0 {
+            // Format: ...,endTime,hitSample
+            let kind = OsuHitObjectKind::Spinner {
+                end_time: parse_int(fields.get(5)?)?,
+            };
+            (kind, sample(6))
+        } else if object_type & TYPE_SLIDER != 0 {
+            // Format: ...,curve,slides,length,edgeSounds,edgeSets,hitSample
+            let params = fields[5..fields.len().min(10)].join(",");
+            (OsuHitObjectKind::Slider { params }, sample(10))
+        } else {
+            (OsuHitObjectKind::Circle, sample(5))
+        };
+
+        Some(OsuHitObject {
+            x: parse_int(fields[0])?,
+            y: parse_int(fields[1])?,
+            time: parse_int(fields[2])?,
+            object_type,
+            hit_sound: fields[4].parse().ok()?,
+            kind,
+            hit_sample,
+        })
+    }
+}
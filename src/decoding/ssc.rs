@@ -0,0 +1,84 @@
+use crate::decoding::decode::{TIMING_TAGS, parse_bpms, parse_offset, parse_stops};
+use crate::decoding::msd::{self, MsdTag, find_tag};
+use crate::error::Error;
+use crate::structs::{Chart, ChartTiming, SmFile};
+use crate::timing::TimingData;
+use crate::utils::{parse_field, parse_list};
+
+impl SmFile {
+    /// Reads StepMania 5 .ssc text: song tags first, then one `#NOTEDATA:;`
+    /// block of tags per chart.
+    pub fn from_ssc_string(content: &str) -> Result<SmFile, Error> {
+        let tags = msd::tokenize(content);
+        let first_chart = tags
+            .iter()
+            .position(|tag| tag.is("NOTEDATA"))
+            .unwrap_or(tags.len());
+
+        let mut sm = SmFile::new();
+        sm.parse_song_tags(&tags[..first_chart])?;
+        sm.keep_song_tags(content, &tags[..first_chart]);
+
+        // Slices start right after each #NOTEDATA, so the first one is empty
+        let chart_blocks = tags[first_chart..].split(|tag| tag.is("NOTEDATA")).skip(1);
+        for (chart_idx, chart_tags) in chart_blocks.enumerate() {
+            let chart = Chart::parse_ssc(chart_tags, chart_idx, &sm)?;
+            sm.charts.push(chart);
+        }
+        Ok(sm)
+    }
+}
+
+impl Chart {
+    fn parse_ssc(tags: &[MsdTag], chart_idx: usize, song: &SmFile) -> Result<Chart, Error> {
+        let mut chart = Chart::new();
+        parse_field(tags, "CHARTNAME", &mut chart.chart_name)?;
+        parse_field(tags, "STEPSTYPE", &mut chart.stepstype)?;
+        parse_field(tags, "DESCRIPTION", &mut chart.description)?;
+        parse_field(tags, "CHARTSTYLE", &mut chart.chart_style)?;
+        parse_field(tags, "DIFFICULTY", &mut chart.difficulty)?;
+        parse_field(tags, "METER", &mut chart.meter)?;
+        parse_field(tags, "CREDIT", &mut chart.credit)?;
+
+        // Format: #RADARVALUES:v1,v2,...;
+        let radar = parse_list(tags, "RADARVALUES", |fields| match fields {
+            [value] => value.parse::<f64>().ok(),
+            _ => None,
+        })?;
+        chart.radar_values = radar.unwrap_or_default();
+
+        chart.column_count = Chart::column_count_for(&chart.stepstype).ok_or_else(|| {
+            Error::UnknownStepsType {
+                chart: chart_idx,
+                stepstype: chart.stepstype.clone(),
+                line: find_tag(tags, "STEPSTYPE").map_or(0, |tag| tag.span.line),
+            }
+        })?;
+
+        // Split timing starts from the song timing, each tag present overrides it
+        let mut own_timing = None;
+        if TIMING_TAGS.iter().any(|name| find_tag(tags, name).is_some()) {
+            let mut timing = ChartTiming {
+                offset: song.offset,
+                bpms: song.bpms.clone(),
+                stops: song.stops.clone(),
+                segments: song.segments.clone(),
+            };
+            parse_offset(tags, &mut timing.offset)?;
+            parse_bpms(tags, &mut timing.bpms)?;
+            parse_stops(tags, &mut timing.stops)?;
+            timing.segments.parse(tags)?;
+            own_timing = Some(timing);
+        }
+
+        chart.timing = own_timing;
+
+        // A chart without #NOTES is kept, just empty
+        if let Some(notes) = find_tag(tags, "NOTES").and_then(|tag| tag.params.first()) {
+            let timing = TimingData::for_chart(song, &chart);
+            chart.parse_note_data(notes, chart_idx, &timing)?;
+        }
+
+        Ok(chart)
+    }
+}
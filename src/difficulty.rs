@@ -0,0 +1,373 @@
+use crate::error::Error;
+use crate::structs::Chart;
+
+// Music rates Etterna rates charts at, from 0.7x to 2.0x in 0.1x steps
+pub const MIN_RATE: f64 = 0.7;
+pub const MAX_RATE: f64 = 2.0;
+
+// Length of the intervals the chart is rated in, in SECONDS
+const INTERVAL_SECONDS: f64 = 0.5;
+// Longest stretch without notes given its own intervals, in SECONDS; longer
+// ones (such as a very long stop) count as this long, when fatigue has worn off
+const MAX_EMPTY_SECONDS: f64 = 60.0;
+// Notes per second of one hand to rating points
+const NPS_SCALE: f64 = 3.4;
+// Notes per second of one column to rating points
+const JACK_SCALE: f64 = 3.4;
+// Chordjack ratings come from the denser hand like stream, scaled down
+const CHORDJACK_SCALE: f64 = 0.85;
+// A rating is the skill level that scores this share of the chart's points
+const TARGET_SCORE: f64 = 0.93;
+// How fast the share of an interval's points drops with the skill deficit
+const SCORE_EXPONENT: f64 = 1.7;
+// Stamina: fatigue kept from one interval to the next, and the rating
+// multiplier without fatigue; full fatigue is a multiplier of 1
+const FATIGUE_DECAY: f64 = 0.98;
+const STAMINA_FLOOR: f64 = 0.8;
+// Charts with fewer notes are too short to show a skill; their ratings are
+// scaled down in proportion
+const SHORT_CHART_NOTES: f64 = 200.0;
+
+/// Etterna skillsets, in the order Etterna lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Skillset {
+    Stream,
+    Jumpstream,
+    Handstream,
+    Stamina,
+    JackSpeed,
+    Chordjack,
+    Technical,
+}
+
+impl Skillset {
+    pub const ALL: [Skillset; 7] = [
+        Skillset::Stream,
+        Skillset::Jumpstream,
+        Skillset::Handstream,
+        Skillset::Stamina,
+        Skillset::JackSpeed,
+        Skillset::Chordjack,
+        Skillset::Technical,
+    ];
+}
+
+/// Difficulty of a chart at one music rate, by Etterna's skillsets: a rating
+/// per skillset, and the overall rating, which is the highest of them. Not
+/// Etterna's MSD; see `Chart::difficulty`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifficultyRating {
+    pub rate: f64,
+    pub overall: f64,
+    pub stream: f64,
+    pub jumpstream: f64,
+    pub handstream: f64,
+    pub stamina: f64,
+    pub jack_speed: f64,
+    pub chordjack: f64,
+    pub technical: f64,
+}
+
+impl DifficultyRating {
+    pub fn skillset(&self, skillset: Skillset) -> f64 {
+        match skillset {
+            Skillset::Stream => self.stream,
+            Skillset::Jumpstream => self.jumpstream,
+            Skillset::Handstream => self.handstream,
+            Skillset::Stamina => self.stamina,
+            Skillset::JackSpeed => self.jack_speed,
+            Skillset::Chordjack => self.chordjack,
+            Skillset::Technical => self.technical,
+        }
+    }
+
+    /// The skillset with the highest rating, the first of `Skillset::ALL`
+    /// on ties.
+    pub fn main_skillset(&self) -> Skillset {
+        Skillset::ALL
+            .into_iter()
+            .rev()
+            .max_by(|a, b| self.skillset(*a).total_cmp(&self.skillset(*b)))
+            .unwrap_or(Skillset::Stream)
+    }
+}
+
+impl Chart {
+    /// Rates the chart at `rate` (0.7 to 2.0) in Etterna's skillsets, with an
+    /// approach modelled on its MinaCalc: the judged notes are split in half-second intervals, each
+    /// interval gets a difficulty per skillset from the note density of the
+    /// busier hand and the patterns in it, and each skillset's rating is the
+    /// skill level that would score 93% on the intervals. Stamina is the
+    /// hardest of stream, jumpstream, handstream and technical, rated lower
+    /// where the chart has not been that dense for long, so it never exceeds
+    /// it. Charts with fewer than 200 notes are rated lower in proportion,
+    /// and stretches without notes count as 60 seconds at most.
+    ///
+    /// Columns are split between two hands, the middle one going to the
+    /// right hand.
+    ///
+    /// This is not MinaCalc and is not compatible with it: ratings are on a
+    /// similar scale but have not been checked against Etterna's MSD, and can
+    /// differ from it by several points or in the main skillset.
+    pub fn difficulty(&self, rate: f64) -> Result<DifficultyRating, Error> {
+        if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+            return Err(Error::UnsupportedRate {
+                rate,
+                min: MIN_RATE,
+                max: MAX_RATE,
+            });
+        }
+        let rows = self.note_rows(rate);
+        let intervals = Interval::split(&rows, self.column_count.max(1));
+        let weights: Vec<f64> = intervals.iter().map(|interval| interval.notes as f64).collect();
+
+        // The density of the busier hand, averaged with the neighbouring intervals
+        let density: Vec<f64> = (0..intervals.len())
+            .map(|idx| {
+                let neighbours = &intervals[idx.saturating_sub(1)..(idx + 2).min(intervals.len())];
+                let total: f64 = neighbours.iter().map(|interval| interval.hand_nps).sum();
+                total / neighbours.len() as f64 * NPS_SCALE
+            })
+            .collect();
+        let skillset = |difficulty: &dyn Fn(&Interval) -> f64| -> Vec<f64> {
+            intervals.iter().zip(&density).map(|(interval, base)| base * difficulty(interval)).collect()
+        };
+
+        let stream = skillset(&Interval::stream_mod);
+        let jumpstream = skillset(&Interval::jumpstream_mod);
+        let handstream = skillset(&Interval::handstream_mod);
+        let chordjack = skillset(&|interval| interval.chordjack_mod() * CHORDJACK_SCALE);
+        let technical = skillset(&Interval::technical_mod);
+        let jack_speed: Vec<f64> = intervals.iter().map(|interval| interval.jack_speed * JACK_SCALE).collect();
+
+        // Stamina: the hardest density skillset, down to `STAMINA_FLOOR` of
+        // it until fatigue builds up
+        let (reference, hardest) = [&stream, &jumpstream, &handstream, &technical]
+            .into_iter()
+            .map(|difficulties| (rating(difficulties, &weights), difficulties))
+            .fold((0.0, &stream), |hardest, skillset| if skillset.0 > hardest.0 { skillset } else { hardest });
+        let mut fatigue = 0.0;
+        let stamina: Vec<f64> = hardest
+            .iter()
+            .map(|difficulty| {
+                let load = if reference > 0.0 { (difficulty / reference).min(1.0) } else { 0.0 };
+                fatigue = fatigue * FATIGUE_DECAY + load * (1.0 - FATIGUE_DECAY);
+                difficulty * (STAMINA_FLOOR + (1.0 - STAMINA_FLOOR) * fatigue)
+            })
+            .collect();
+
+        let length_scale = (weights.iter().sum::<f64>() / SHORT_CHART_NOTES).min(1.0);
+        let rate_skillset = |difficulties: &[f64]| rating(difficulties, &weights) * length_scale;
+        let mut result = DifficultyRating {
+            rate,
+            overall: 0.0,
+            stream: rate_skillset(&stream),
+            jumpstream: rate_skillset(&jumpstream),
+            handstream: rate_skillset(&handstream),
+            stamina: rate_skillset(&stamina),
+            jack_speed: rate_skillset(&jack_speed),
+            chordjack: rate_skillset(&chordjack),
+            technical: rate_skillset(&technical),
+        };
+        result.overall = result.skillset(result.main_skillset());
+        Ok(result)
+    }
+
+    /// `difficulty` at every rate from 0.7x to 2.0x, in 0.1x steps.
+    pub fn difficulty_by_rate(&self) -> Vec<DifficultyRating> {
+        let (first, last) = ((MIN_RATE * 10.0).round() as u32, (MAX_RATE * 10.0).round() as u32);
+        (first..=last)
+            .filter_map(|tenths| self.difficulty(tenths as f64 / 10.0).ok())
+            .collect()
+    }
+
+    /// (time in SECONDS at `rate`, column bits) of each judged row with notes.
+    pub(crate) fn note_rows(&self, rate: f64) -> Vec<(f64, u64)> {
+        self.measures
+            .iter()
+            .flat_map(|measure| measure.beats.iter())
+            .filter(|beat| beat.judged)
+            .filter_map(|beat| {
+                let columns = beat
+                    .notes
+                    .iter()
+                    .enumerate()
+                    .filter(|(column, note)| note.is_note() && *column < 64)
+                    .fold(0u64, |bits, (column, _)| bits | 1 << column);
+                (columns != 0).then_some((beat.time / 1000.0 / rate, columns))
+            })
+            .collect()
+    }
+}
+
+/// Note and pattern counts of one interval.
+#[derive(Debug, Clone, Default)]
+struct Interval {
+    notes: u32,
+    rows: u32,
+    jumps: u32,         // Rows with 2 notes
+    hands: u32,         // Rows with 3 notes or more
+    jack_notes: u32,    // Notes on a column the previous row also hit
+    hand_nps: f64,      // Notes per second of the busier hand
+    jack_speed: f64,    // Average of 1 / time since the previous note on the same column
+    gap_variation: f64, // Coefficient of variation of the time between notes of a hand
+}
+
+impl Interval {
+    fn split(rows: &[(f64, u64)], column_count: u32) -> Vec<Interval> {
+        let Some(&(start, _)) = rows.first() else {
+            return Vec::new();
+        };
+        let left_hand = (1u64 << (column_count / 2)) - 1;
+        // Interval of each row, with stretches without notes cut to
+        // `MAX_EMPTY_SECONDS`, so there are at most that many empty intervals per row
+        let mut cut = 0.0;
+        let indices: Vec<usize> = rows
+            .iter()
+            .scan(start, |previous, &(time, _)| {
+                cut += (time - *previous - MAX_EMPTY_SECONDS).max(0.0);
+                *previous = time;
+                Some(((time - start - cut) / INTERVAL_SECONDS) as usize)
+            })
+            .collect();
+        let mut intervals = vec![Interval::default(); indices[indices.len() - 1] + 1];
+
+        // Per hand: sum of notes per second since the hand's previous row, and row count
+        let mut hand_speeds = vec![[(0.0, 0u32); 2]; intervals.len()];
+        let mut hand_gaps: Vec<Vec<f64>> = vec![Vec::new(); intervals.len()];
+        let mut jack_speeds = vec![(0.0, 0u32); intervals.len()];
+        let mut previous_hand_time = [None::<f64>; 2];
+        let mut previous_column_time = [None::<f64>; 64];
+        let mut previous_columns = 0u64;
+
+        for (&(time, columns), &idx) in rows.iter().zip(&indices) {
+            let interval = &mut intervals[idx];
+            let count = columns.count_ones();
+            interval.notes += count;
+            interval.rows += 1;
+            match count {
+                1 => {}
+                2 => interval.jumps += 1,
+                _ => interval.hands += 1,
+            }
+            interval.jack_notes += (columns & previous_columns).count_ones();
+            previous_columns = columns;
+
+            for (hand, bits) in [columns & left_hand, columns & !left_hand].into_iter().enumerate() {
+                if bits == 0 {
+                    continue;
+                }
+                if let Some(previous) = previous_hand_time[hand] {
+                    let gap = (time - previous).max(0.001);
+                    hand_gaps[idx].push(gap);
+                    hand_speeds[idx][hand].0 += bits.count_ones() as f64 / gap;
+                    hand_speeds[idx][hand].1 += 1;
+                }
+                previous_hand_time[hand] = Some(time);
+            }
+            for (column, previous) in previous_column_time.iter_mut().enumerate() {
+                if columns & 1 << column == 0 {
+                    continue;
+                }
+                if let Some(previous) = *previous {
+                    jack_speeds[idx].0 += 1.0 / (time - previous).max(0.001);
+                    jack_speeds[idx].1 += 1;
+                }
+                *previous = Some(time);
+            }
+        }
+
+        for (idx, interval) in intervals.iter_mut().enumerate() {
+            interval.hand_nps = hand_speeds[idx]
+                .iter()
+                .map(|(speeds, count)| if *count > 0 { speeds / *count as f64 } else { 0.0 })
+                .fold(0.0, f64::max);
+            let (speeds, count) = jack_speeds[idx];
+            if count > 0 {
+                interval.jack_speed = speeds / count as f64;
+            }
+            interval.gap_variation = variation(&hand_gaps[idx]);
+        }
+        intervals
+    }
+
+    fn share(&self, count: u32) -> f64 {
+        if self.rows == 0 { 0.0 } else { count as f64 / self.rows as f64 }
+    }
+
+    fn jack_share(&self) -> f64 {
+        if self.notes == 0 { 0.0 } else { self.jack_notes as f64 / self.notes as f64 }
+    }
+
+    /// Single notes without jacks.
+    fn stream_mod(&self) -> f64 {
+        let singles = self.rows - self.jumps - self.hands;
+        (0.55 + 0.45 * self.share(singles)) * (1.0 - 0.6 * self.jack_share())
+    }
+
+    /// Streams with up to one jump every other row and no hands.
+    fn jumpstream_mod(&self) -> f64 {
+        let jumps = (0.55 + 0.9 * self.share(self.jumps).min(0.5)) * (1.0 - 0.5 * self.share(self.hands));
+        jumps * (1.0 - 0.6 * self.jack_share())
+    }
+
+    /// Streams with up to one hand every other row.
+    fn handstream_mod(&self) -> f64 {
+        (0.55 + 0.9 * self.share(self.hands).min(0.5)) * (1.0 - 0.6 * self.jack_share())
+    }
+
+    /// Chords hit again on the next row.
+    fn chordjack_mod(&self) -> f64 {
+        let chords = (2.0 * self.share(self.jumps + self.hands)).min(1.0);
+        0.5 + 0.5 * chords * (2.0 * self.jack_share()).min(1.0)
+    }
+
+    /// Uneven rhythms within a hand.
+    fn technical_mod(&self) -> f64 {
+        (0.6 + 0.6 * self.gap_variation.min(0.5)) * (1.0 - 0.3 * self.jack_share())
+    }
+}
+
+/// Standard deviation of `values` over their mean, 0 for fewer than 2 values.
+fn variation(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    variance.sqrt() / mean
+}
+
+/// The lowest skill level that scores `TARGET_SCORE` of the points, each
+/// interval being worth its `weights` (note count) and giving all of them to
+/// a player at or above its difficulty.
+fn rating(difficulties: &[f64], weights: &[f64]) -> f64 {
+    let total: f64 = weights.iter().sum();
+    let hardest = difficulties.iter().copied().fold(0.0, f64::max);
+    if total == 0.0 || hardest == 0.0 {
+        return 0.0;
+    }
+    let score = |skill: f64| -> f64 {
+        difficulties
+            .iter()
+            .zip(weights)
+            .map(|(difficulty, weight)| {
+                if *difficulty <= skill { *weight } else { weight * (skill / difficulty).powf(SCORE_EXPONENT) }
+            })
+            .sum()
+    };
+
+    let (mut low, mut high) = (0.0, hardest);
+    for _ in 0..50 {
+        let skill = (low + high) / 2.0;
+        if score(skill) >= TARGET_SCORE * total {
+            high = skill;
+        } else {
+            low = skill;
+        }
+    }
+    high
+}
@@ -0,0 +1,276 @@
+use crate::error::Error;
+use crate::structs::{Chart, Measure, NoteType, RawTag, SmFile, SmWriteSettings, TimingSegments};
+use crate::timing::{ROWS_PER_MEASURE, Row, Snap};
+use std::path::PathBuf;
+
+impl SmFile {
+    /// Writes the song as .sm text: metadata and timing tags and the song
+    /// tags kept in `other_tags`, in the file order recorded in `tag_order`,
+    /// then one `#NOTES` block per chart.
+    ///
+    /// .ssc-only data (chart split timing, chart names, styles and credits)
+    /// has no .sm equivalent and is left out.
+    pub fn to_sm_string(&self) -> String {
+        self.to_sm_string_with(&SmWriteSettings::new())
+    }
+
+    /// `to_sm_string` with the options in `settings`.
+    pub fn to_sm_string_with(&self, settings: &SmWriteSettings) -> String {
+        let mut sm = String::new();
+        for tag in in_file_order(self.song_tags(), &self.tag_order) {
+            sm.push_str(&tag.text);
+            if !tag.text.ends_with(';') {
+                sm.push(';');
+            }
+            sm.push('\n');
+        }
+
+        for chart in &self.charts {
+            sm.push('\n');
+            chart.write_notes(&mut sm, settings);
+        }
+        sm
+    }
+
+    /// Writes `to_sm_string` to `path`.
+    pub fn write_sm(&self, path: PathBuf) -> Result<(), Error> {
+        self.write_sm_with(path, &SmWriteSettings::new())
+    }
+
+    /// Writes `to_sm_string_with(settings)` to `path`.
+    pub fn write_sm_with(&self, path: PathBuf, settings: &SmWriteSettings) -> Result<(), Error> {
+        std::fs::write(path, self.to_sm_string_with(settings))?;
+        Ok(())
+    }
+
+    /// All song tags, in the order StepMania writes them, each with whether
+    /// it holds a value other than the default.
+    fn song_tags(&self) -> Vec<(RawTag, bool)> {
+        let metadata = &self.metadata;
+        let text = |name: &str, value: &str| (tag(name, &escape(value)), !value.is_empty());
+        let seconds = |name: &str, ms: f64| (tag(name, &format_decimal(ms / 1000.0)), ms != 0.0);
+        let mut tags = vec![
+            text("TITLE", &metadata.title),
+            text("SUBTITLE", &metadata.subtitle),
+            text("ARTIST", &metadata.artist),
+            text("TITLETRANSLIT", &metadata.title_translit),
+            text("SUBTITLETRANSLIT", &metadata.subtitle_translit),
+            text("ARTISTTRANSLIT", &metadata.artist_translit),
+            text("GENRE", &metadata.genre),
+            text("CREDIT", &metadata.credit),
+            text("MUSIC", &metadata.music),
+            text("BANNER", &metadata.banner),
+            text("BACKGROUND", &metadata.background),
+            text("LYRICSPATH", &metadata.lyrics_path),
+            text("CDTITLE", &metadata.cd_title),
+            seconds("SAMPLESTART", metadata.sample_start),
+            seconds("SAMPLELENGTH", metadata.sample_length),
+            text("SELECTABLE", &metadata.selectable),
+            // Format: #OFFSET:seconds; #BPMS:beat=bpm,...; #STOPS:beat=seconds,...;
+            seconds("OFFSET", self.offset),
+            list("BPMS", &self.bpms, |bpm| format_decimal(*bpm)),
+            list("STOPS", &self.stops, |seconds| format_decimal(*seconds)),
+        ];
+        tags.extend(self.segments.tags());
+        tags.extend(self.other_tags.iter().map(|tag| (tag.clone(), true)));
+        tags
+    }
+}
+
+impl TimingSegments {
+    /// Tags of all segment lists, each with whether its list has entries.
+    fn tags(&self) -> Vec<(RawTag, bool)> {
+        let beats = |length: &Row| format_beat(*length);
+        let number = |value: &f64| format_decimal(*value);
+        let speeds: Vec<String> = self
+            .speeds
+            .iter()
+            .map(|speed| {
+                format!(
+                    "{}={}={}={}",
+                    format_beat(speed.row),
+                    format_decimal(speed.ratio),
+                    format_decimal(speed.duration),
+                    if speed.in_seconds { 1 } else { 0 }
+                )
+            })
+            .collect();
+        let combos: Vec<String> = self
+            .combos
+            .iter()
+            .map(|(row, hit, miss)| format!("{}={}={}", format_beat(*row), hit, miss))
+            .collect();
+        let signatures: Vec<String> = self
+            .time_signatures
+            .iter()
+            .map(|(row, num, den)| format!("{}={}={}", format_beat(*row), num, den))
+            .collect();
+
+        vec![
+            list("DELAYS", &self.delays, number),
+            list("WARPS", &self.warps, beats),
+            (tag("SPEEDS", &speeds.join(",")), !speeds.is_empty()),
+            list("SCROLLS", &self.scrolls, number),
+            list("FAKES", &self.fakes, beats),
+            list("TICKCOUNTS", &self.tick_counts, u32::to_string),
+            (tag("COMBOS", &combos.join(",")), !combos.is_empty()),
+            list("LABELS", &self.labels, |label| escape(label)),
+            (tag("TIMESIGNATURES", &signatures.join(",")), !signatures.is_empty()),
+        ]
+    }
+}
+
+impl Chart {
+    /// Writes the chart's `#NOTES` block, each measure at its fewest lines.
+    fn write_notes(&self, sm: &mut String, settings: &SmWriteSettings) {
+        let radar_values = if settings.refresh_radar_values {
+            // One set per player
+            self.compute_radar_values().to_vec().repeat(2)
+        } else {
+            self.radar_values.clone()
+        };
+        let radar: Vec<String> = radar_values.iter().map(|v| format_decimal(*v)).collect();
+
+        sm.push_str(&format!("//---------------{} - {}----------------\n", self.stepstype, self.description));
+        sm.push_str("#NOTES:\n");
+        sm.push_str(&format!("     {}:\n", escape(&self.stepstype)));
+        sm.push_str(&format!("     {}:\n", escape(&self.description)));
+        sm.push_str(&format!("     {}:\n", escape(&self.difficulty)));
+        sm.push_str(&format!("     {}:\n", self.meter));
+        sm.push_str(&format!("     {}:\n", radar.join(",")));
+
+        for (measure_idx, measure) in self.measures.iter().enumerate() {
+            if measure_idx > 0 {
+                sm.push_str(",\n");
+            }
+            for line in measure.note_lines(measure_idx, self.column_count as usize) {
+                sm.push_str(&line);
+                sm.push('\n');
+            }
+        }
+        sm.push_str(";\n");
+    }
+}
+
+impl Measure {
+    /// Note lines of this measure (number `measure_idx` in its chart) at the
+    /// fewest lines that keep every note on its row: the coarsest snap that
+    /// fits, or an exact count such as 7 for notes between 192nds.
+    fn note_lines(&self, measure_idx: usize, column_count: usize) -> Vec<String> {
+        let start = Row::in_measure(measure_idx, 0, 1);
+
+        // Position of each non-empty line as a fraction of the measure,
+        // reduced by Row::new
+        let positions: Vec<(Row, &[NoteType])> = self
+            .beats
+            .iter()
+            .filter(|beat| beat.notes.iter().any(|note| *note != NoteType::Empty))
+            .map(|beat| {
+                let offset = beat.row - start;
+                let position = Row::new(offset.numer(), offset.denom() * ROWS_PER_MEASURE as i64);
+                (position, beat.notes.as_slice())
+            })
+            .collect();
+
+        let needed = positions
+            .iter()
+            .fold(1, |lines, (position, _)| lcm(lines, position.denom()));
+        let line_count = Snap::ALL
+            .iter()
+            .map(|snap| snap.per_measure() as i64)
+            .find(|count| count % needed == 0)
+            .unwrap_or(needed);
+
+        let mut lines = vec![vec![NoteType::Empty.to_char(); column_count]; line_count as usize];
+        for (position, notes) in positions {
+            let line_idx = position.numer() * line_count / position.denom();
+            let Some(line) = usize::try_from(line_idx).ok().and_then(|idx| lines.get_mut(idx)) else {
+                continue;
+            };
+            for (column, note) in notes.iter().enumerate().take(column_count) {
+                line[column] = note.to_char();
+            }
+        }
+        lines.into_iter().map(|line| line.into_iter().collect()).collect()
+    }
+}
+
+fn lcm(a: i64, b: i64) -> i64 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+fn tag(name: &str, value: &str) -> RawTag {
+    RawTag {
+        name: name.to_string(),
+        text: format!("#{}:{};", name, value),
+    }
+}
+
+/// The tags named in `order`, the source file's tags, in that order, even
+/// if empty; then, in their place in `tags`, the others holding a value.
+fn in_file_order(mut tags: Vec<(RawTag, bool)>, order: &[String]) -> Vec<RawTag> {
+    let mut ordered = Vec::with_capacity(tags.len());
+    for name in order {
+        if let Some(idx) = tags.iter().position(|(tag, _)| tag.name.eq_ignore_ascii_case(name)) {
+            ordered.push(tags.remove(idx).0);
+        }
+    }
+    ordered.extend(tags.into_iter().filter(|(_, has_value)| *has_value).map(|(tag, _)| tag));
+    ordered
+}
+
+/// `#NAME:beat=value,...;` tag of a (row, value) list, and whether it has entries.
+fn list<T>(name: &str, pairs: &[(Row, T)], value: impl Fn(&T) -> String) -> (RawTag, bool) {
+    (tag(name, &format_pairs(pairs, value)), !pairs.is_empty())
+}
+
+/// `beat=value,...` with beats converted back from rows.
+fn format_pairs<T>(pairs: &[(Row, T)], value: impl Fn(&T) -> String) -> String {
+    pairs
+        .iter()
+        .map(|(row, v)| format!("{}={}", format_beat(*row), value(v)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Beat of `row` with 3 decimals, or 6 if 3 would not read back as `row`.
+/// Whole rows read back from 3 decimals (`0.333` for a 12th), as StepMania
+/// writes them.
+fn format_beat(row: Row) -> String {
+    let short = format!("{:.3}", row.beats());
+    if Row::parse_beat(&short) == Some(row) {
+        short
+    } else {
+        format!("{:.6}", row.beats())
+    }
+}
+
+/// `value` with 3 decimals, or 6 if 3 would lose precision, as StepMania
+/// writes them.
+fn format_decimal(value: f64) -> String {
+    let short = format!("{:.3}", value);
+    let tolerance = 1e-9 * value.abs().max(1.0);
+    if short.parse::<f64>().is_ok_and(|parsed| (parsed - value).abs() <= tolerance) {
+        short
+    } else {
+        format!("{:.6}", value)
+    }
+}
+
+/// Escapes characters the MSD tokenizer would read as syntax: `:`, `;`, `\`
+/// and the `//` of a comment.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if matches!(c, ':' | ';' | '\\') || (c == '/' && chars.peek() == Some(&'/')) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,172 @@
+use crate::error::Error;
+use crate::structs::{OsuBeatmap, OsuEvent, OsuHitObject, OsuHitObjectKind, OsuTimingPoint};
+use std::fmt;
+use std::path::PathBuf;
+
+impl OsuBeatmap {
+    /// Writes the beatmap's .osu text (its `Display` output) to `path`.
+    pub fn write_osu(&self, path: PathBuf) -> Result<(), Error> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+/// .osu text, in the section and key order the osu! editor writes.
+impl fmt::Display for OsuBeatmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "osu file format v{}", self.format_version)?;
+        writeln!(f)?;
+
+        let general = &self.general;
+        writeln!(f, "[General]")?;
+        writeln!(f, "AudioFilename: {}", general.audio_filename)?;
+        writeln!(f, "AudioLeadIn: {}", general.audio_lead_in)?;
+        writeln!(f, "PreviewTime: {}", general.preview_time)?;
+        writeln!(f, "Countdown: {}", general.countdown)?;
+        writeln!(f, "SampleSet: {}", general.sample_set)?;
+        writeln!(f, "StackLeniency: {}", general.stack_leniency)?;
+        writeln!(f, "Mode: {}", general.mode)?;
+        writeln!(f, "LetterboxInBreaks: {}", flag(general.letterbox_in_breaks))?;
+        writeln!(f, "SpecialStyle: {}", flag(general.special_style))?;
+        writeln!(f, "WidescreenStoryboard: {}", flag(general.widescreen_storyboard))?;
+        for (key, value) in &general.other {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        writeln!(f)?;
+
+        let editor = &self.editor;
+        writeln!(f, "[Editor]")?;
+        if !editor.bookmarks.is_empty() {
+            let bookmarks: Vec<String> = editor.bookmarks.iter().map(i32::to_string).collect();
+            writeln!(f, "Bookmarks: {}", bookmarks.join(","))?;
+        }
+        writeln!(f, "DistanceSpacing: {}", editor.distance_spacing)?;
+        writeln!(f, "BeatDivisor: {}", editor.beat_divisor)?;
+        writeln!(f, "GridSize: {}", editor.grid_size)?;
+        writeln!(f, "TimelineZoom: {}", editor.timeline_zoom)?;
+        for (key, value) in &editor.other {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        writeln!(f)?;
+
+        let metadata = &self.metadata;
+        writeln!(f, "[Metadata]")?;
+        writeln!(f, "Title:{}", metadata.title)?;
+        writeln!(f, "TitleUnicode:{}", metadata.title_unicode)?;
+        writeln!(f, "Artist:{}", metadata.artist)?;
+        writeln!(f, "ArtistUnicode:{}", metadata.artist_unicode)?;
+        writeln!(f, "Creator:{}", metadata.creator)?;
+        writeln!(f, "Version:{}", metadata.version)?;
+        writeln!(f, "Source:{}", metadata.source)?;
+        writeln!(f, "Tags:{}", metadata.tags.join(" "))?;
+        writeln!(f, "BeatmapID:{}", metadata.beatmap_id)?;
+        writeln!(f, "BeatmapSetID:{}", metadata.beatmap_set_id)?;
+        for (key, value) in &metadata.other {
+            writeln!(f, "{}:{}", key, value)?;
+        }
+        writeln!(f)?;
+
+        let difficulty = &self.difficulty;
+        writeln!(f, "[Difficulty]")?;
+        writeln!(f, "HPDrainRate:{}", difficulty.hp_drain_rate)?;
+        writeln!(f, "CircleSize:{}", difficulty.circle_size)?;
+        writeln!(f, "OverallDifficulty:{}", difficulty.overall_difficulty)?;
+        writeln!(f, "ApproachRate:{}", difficulty.approach_rate)?;
+        writeln!(f, "SliderMultiplier:{}", difficulty.slider_multiplier)?;
+        writeln!(f, "SliderTickRate:{}", difficulty.slider_tick_rate)?;
+        for (key, value) in &difficulty.other {
+            writeln!(f, "{}:{}", key, value)?;
+        }
+        writeln!(f)?;
+
+        // Storyboard lines are not split by layer; their own fields name it
+        writeln!(f, "[Events]")?;
+        writeln!(f, "//Background and Video events")?;
+        for event in &self.events {
+            match event {
+                OsuEvent::Background { filename, x, y } => writeln!(f, "0,0,\"{}\",{},{}", filename, x, y)?,
+                OsuEvent::Video { start_time, filename, x, y } => {
+                    writeln!(f, "Video,{},\"{}\",{},{}", start_time, filename, x, y)?
+                }
+                _ => {}
+            }
+        }
+        writeln!(f, "//Break Periods")?;
+        for event in &self.events {
+            if let OsuEvent::Break { start_time, end_time } = event {
+                writeln!(f, "2,{},{}", start_time, end_time)?;
+            }
+        }
+        writeln!(f, "//Storyboard Layer 0 (Background)")?;
+        for event in &self.events {
+            if let OsuEvent::Other(line) = event {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        writeln!(f, "//Storyboard Layer 1 (Fail)")?;
+        writeln!(f, "//Storyboard Layer 2 (Pass)")?;
+        writeln!(f, "//Storyboard Layer 3 (Foreground)")?;
+        writeln!(f, "//Storyboard Sound Samples")?;
+        writeln!(f)?;
+
+        writeln!(f, "[TimingPoints]")?;
+        for point in &self.timing_points {
+            writeln!(f, "{}", point)?;
+        }
+        writeln!(f)?;
+
+        if !self.colours.is_empty() {
+            writeln!(f, "[Colours]")?;
+            for (key, [r, g, b]) in &self.colours {
+                writeln!(f, "{} : {},{},{}", key, r, g, b)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "[HitObjects]")?;
+        for object in &self.hit_objects {
+            writeln!(f, "{}", object)?;
+        }
+        Ok(())
+    }
+}
+
+/// Format: time,beatLength,meter,sampleSet,sampleIndex,volume,uninherited,effects
+impl fmt::Display for OsuTimingPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{},{},{},{}",
+            self.time,
+            self.beat_length,
+            self.meter,
+            self.sample_set,
+            self.sample_index,
+            self.volume,
+            flag(self.uninherited),
+            self.effects
+        )
+    }
+}
+
+/// Format: x,y,time,type,hitSound,objectParams...,hitSample
+impl fmt::Display for OsuHitObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{},{}", self.x, self.y, self.time, self.object_type, self.hit_sound)?;
+        match &self.kind {
+            // osu!mania long notes join the end time and the sample with ':'
+            OsuHitObjectKind::Hold { end_time } => return write!(f, ",{}:{}", end_time, self.hit_sample),
+            OsuHitObjectKind::Spinner { end_time } => write!(f, ",{}", end_time)?,
+            OsuHitObjectKind::Slider { params } => write!(f, ",{}", params)?,
+            OsuHitObjectKind::Circle => {}
+        }
+        if !self.hit_sample.is_empty() {
+            write!(f, ",{}", self.hit_sample)?;
+        }
+        Ok(())
+    }
+}
+
+fn flag(value: bool) -> u8 {
+    if value { 1 } else { 0 }
+}
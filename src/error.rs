@@ -0,0 +1,120 @@
+use std::fmt;
+
+/// Errors returned while reading StepMania and osu! files.
+///
+/// `line` and `column` are 1-based positions in the source text; `chart` and
+/// `measure` are 0-based indices in file order.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    MissingTag {
+        tag: String,
+    },
+    MalformedValue {
+        tag: String,
+        text: String,
+        line: usize,
+        column: usize,
+    },
+    MalformedPair {
+        tag: String,
+        text: String,
+        line: usize,
+        column: usize,
+    },
+    UnknownStepsType {
+        chart: usize,
+        stepstype: String,
+        line: usize,
+    },
+    BadNoteLine {
+        chart: usize,
+        measure: usize,
+        text: String,
+        line: usize,
+        column: usize,
+    },
+    ColumnCountMismatch {
+        chart: usize,
+        measure: usize,
+        expected: u32,
+        found: usize,
+        line: usize,
+    },
+    MalformedOsuLine {
+        section: String, // Empty for the "osu file format" header
+        text: String,
+        line: usize,
+    },
+    UnsupportedOsuMap {
+        reason: String,
+    },
+    UnsupportedRate {
+        rate: f64,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::MissingTag { tag } => write!(f, "missing required tag #{}", tag),
+            Error::MalformedValue { tag, text, line, column } => write!(
+                f,
+                "line {}, column {}: malformed #{} value '{}'",
+                line, column, tag, text
+            ),
+            Error::MalformedPair { tag, text, line, column } => write!(
+                f,
+                "line {}, column {}: malformed #{} entry '{}'",
+                line, column, tag, text
+            ),
+            Error::UnknownStepsType { chart, stepstype, line } => write!(
+                f,
+                "line {}: chart {} has unknown stepstype '{}'",
+                line, chart, stepstype
+            ),
+            Error::BadNoteLine { chart, measure, text, line, column } => write!(
+                f,
+                "line {}, column {}: chart {} measure {} has invalid note line '{}'",
+                line, column, chart, measure, text
+            ),
+            Error::ColumnCountMismatch { chart, measure, expected, found, line } => write!(
+                f,
+                "line {}: chart {} measure {} has a {}-column note line, expected {} columns",
+                line, chart, measure, found, expected
+            ),
+            Error::MalformedOsuLine { section, text, line } if section.is_empty() => write!(
+                f,
+                "line {}: expected an 'osu file format' header, found '{}'",
+                line, text
+            ),
+            Error::MalformedOsuLine { section, text, line } => write!(
+                f,
+                "line {}: malformed [{}] line '{}'",
+                line, section, text
+            ),
+            Error::UnsupportedOsuMap { reason } => write!(f, "cannot convert osu! map: {}", reason),
+            Error::UnsupportedRate { rate, min, max } => {
+                write!(f, "music rate {}x is outside {}x to {}x", rate, min, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
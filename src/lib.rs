@@ -1,4 +1,12 @@
+pub mod analysis;
 pub mod decoding;
+pub mod encoding;
 pub mod structs;
 pub mod converter;
+pub mod difficulty;
+pub mod radar;
+pub mod error;
+pub mod timing;
 mod utils;
+
+pub use error::Error;
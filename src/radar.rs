@@ -0,0 +1,167 @@
+use crate::structs::{Chart, NoteType};
+use crate::timing::{ROWS_PER_BEAT, Row};
+
+// Radar values top out at these rates
+const STREAM_NOTES_PER_SECOND: f64 = 7.0;
+const VOLTAGE_BEATS_PER_SECOND: f64 = 10.0;
+const CHAOS_NOTES_PER_SECOND: f64 = 2.0;
+// Voltage takes the densest window of this many beats
+const VOLTAGE_WINDOW_BEATS: f64 = 8.0;
+
+/// StepMania's radar categories of a chart: the five groove radar values,
+/// from 0 to 1, and the note counts StepMania 5 appends to them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RadarValues {
+    pub stream: f64,  // Average note density
+    pub voltage: f64, // Peak note density
+    pub air: f64,     // Jumps per second
+    pub freeze: f64,  // Holds per second
+    pub chaos: f64,   // Notes off the 8th note grid per second
+    pub notes: u32,
+    pub taps_and_holds: u32, // Rows with a tap or a hold head
+    pub jumps: u32,          // Rows with 2 taps or hold heads or more
+    pub holds: u32,
+    pub mines: u32,
+    pub hands: u32, // Rows with 3 columns or more pressed, holds included
+    pub rolls: u32,
+    pub lifts: u32,
+    pub fakes: u32,
+}
+
+impl RadarValues {
+    /// Values in StepMania 5's `RadarCategory` order, as written in #NOTES.
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![
+            self.stream,
+            self.voltage,
+            self.air,
+            self.freeze,
+            self.chaos,
+            self.notes as f64,
+            self.taps_and_holds as f64,
+            self.jumps as f64,
+            self.holds as f64,
+            self.mines as f64,
+            self.hands as f64,
+            self.rolls as f64,
+            self.lifts as f64,
+            self.fakes as f64,
+        ]
+    }
+}
+
+impl Chart {
+    /// Computes the chart's radar values from its notes, as StepMania does.
+    ///
+    /// The song length is the time of the last note, since the music's
+    /// length is not known; StepMania uses the music file when it has one.
+    /// Notes in warps and #FAKES ranges only count as fakes.
+    pub fn compute_radar_values(&self) -> RadarValues {
+        let mut radar = RadarValues::default();
+        let beats: Vec<_> = self.measures.iter().flat_map(|measure| measure.beats.iter()).collect();
+
+        // Rows of the judged taps and hold heads, for the densities; hold
+        // heads are there twice, as StepMania counts them as taps and as holds
+        let mut density_rows: Vec<Row> = Vec::new();
+        let mut chaos_notes = 0;
+        let mut last_time: f64 = 0.0;
+        let mut last_row = Row::ZERO; // Of any note, hold tails included
+        // Columns with a hold or roll started and not yet ended
+        let mut held = vec![false; self.column_count as usize];
+
+        for beat in beats {
+            if held.len() < beat.notes.len() {
+                held.resize(beat.notes.len(), false);
+            }
+            let mut row_heads = 0;
+            let mut holds_here = 0;
+            for (column, note) in beat.notes.iter().enumerate() {
+                let head = matches!(note, NoteType::Tap | NoteType::HoldHead | NoteType::RollHead);
+                match note {
+                    NoteType::HoldHead | NoteType::RollHead => held[column] = true,
+                    NoteType::Tail => {
+                        held[column] = false;
+                        holds_here += 1;
+                    }
+                    _ => {}
+                }
+                if held[column] {
+                    holds_here += 1;
+                }
+
+                if !beat.judged {
+                    if note.is_note() || *note == NoteType::Fake {
+                        radar.fakes += 1;
+                    }
+                    continue;
+                }
+                if *note != NoteType::Empty {
+                    last_row = last_row.max(beat.row);
+                }
+                if note.is_note() {
+                    radar.notes += 1;
+                    last_time = last_time.max(beat.time);
+                }
+                if head {
+                    row_heads += 1;
+                    density_rows.push(beat.row);
+                    if !on_eighths(beat.row) {
+                        chaos_notes += 1;
+                    }
+                }
+                match note {
+                    NoteType::HoldHead => {
+                        radar.holds += 1;
+                        density_rows.push(beat.row);
+                    }
+                    NoteType::RollHead => radar.rolls += 1,
+                    NoteType::Lift => radar.lifts += 1,
+                    NoteType::Mine => radar.mines += 1,
+                    NoteType::Fake => radar.fakes += 1,
+                    _ => {}
+                }
+            }
+
+            if row_heads > 0 {
+                radar.taps_and_holds += 1;
+            }
+            if row_heads >= 2 {
+                radar.jumps += 1;
+            }
+            // Like StepMania, holds are only added to rows short of 3 heads, and
+            // a hold counts on its head's row as well as the head itself
+            if row_heads >= 3 || (row_heads > 0 && row_heads + holds_here >= 3) {
+                radar.hands += 1;
+            }
+        }
+
+        let seconds = last_time / 1000.0;
+        if seconds <= 0.0 {
+            return radar;
+        }
+        radar.stream = (density_rows.len() as f64 / seconds / STREAM_NOTES_PER_SECOND).min(1.0);
+
+        let window = Row::from_rows((VOLTAGE_WINDOW_BEATS * ROWS_PER_BEAT) as i64);
+        let mut peak: f64 = 0.0;
+        let mut start = Row::ZERO;
+        while start <= last_row {
+            let end = start + window;
+            let in_window = |row: &Row| *row >= start && *row < end;
+            let notes = density_rows.iter().filter(|row| in_window(row)).count() as f64;
+            peak = peak.max(notes / VOLTAGE_WINDOW_BEATS);
+            start = end;
+        }
+        let beats_per_second = last_row.beats() / seconds;
+        radar.voltage = (peak * beats_per_second / VOLTAGE_BEATS_PER_SECOND).min(1.0);
+
+        radar.air = (radar.jumps as f64 / seconds).min(1.0);
+        radar.freeze = (radar.holds as f64 / seconds).min(1.0);
+        radar.chaos = (chaos_notes as f64 / seconds / CHAOS_NOTES_PER_SECOND).min(1.0);
+        radar
+    }
+}
+
+/// Whether `row` is on the 8th note grid (a multiple of half a beat).
+fn on_eighths(row: Row) -> bool {
+    row.is_whole() && row.numer() % (ROWS_PER_BEAT as i64 / 2) == 0
+}
@@ -1,11 +1,17 @@
-use crate::utils::parse_field;
+use crate::decoding::msd::MsdTag;
+use crate::error::Error;
+use crate::timing::{Row, Snap};
+use crate::utils::{parse_field, parse_seconds};
 #[derive(Debug, Clone)]
 pub struct SmFile {
     pub metadata: Metadata,
-    pub offset: f64, // Time in MILLISECONDS
-    pub bpms: Vec<(f64, f64)>,  // (row, bpm) - row position and BPM value
-    pub stops: Vec<(f64, f64)>, // (row, duration) - row position and duration in seconds
+    pub offset: f64, // #OFFSET in MILLISECONDS - beat 0 plays at -offset in the audio
+    pub bpms: Vec<(Row, f64)>,  // (row, bpm) - row position and BPM value
+    pub stops: Vec<(Row, f64)>, // (row, duration) - row position and duration in seconds
+    pub segments: TimingSegments,
     pub charts: Vec<Chart>,
+    pub other_tags: Vec<RawTag>, // Song tags the decoder does not read, in file order
+    pub tag_order: Vec<String>,  // Names of all song tags in file order, for writers
 }
 
 impl SmFile {
@@ -15,22 +21,100 @@ impl SmFile {
             offset: 0.0,
             bpms: Vec::new(),
             stops: Vec::new(),
+            segments: TimingSegments::new(),
             charts: Vec::new(),
+            other_tags: Vec::new(),
+            tag_order: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+impl Default for SmFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A tag kept exactly as written in the source file, so writers can emit it
+/// back unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawTag {
+    pub name: String, // As written, without the leading '#'
+    pub text: String, // Source text from '#' through ';'
+}
+
+/// StepMania 5 timing tags beyond #OFFSET, #BPMS and #STOPS.
+/// Rows are StepMania rows (48 per beat), like `SmFile::bpms`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingSegments {
+    pub delays: Vec<(Row, f64)>,                // (row, duration in seconds)
+    pub warps: Vec<(Row, Row)>,                 // (row, length in rows)
+    pub speeds: Vec<SpeedSegment>,
+    pub scrolls: Vec<(Row, f64)>,               // (row, scroll ratio)
+    pub fakes: Vec<(Row, Row)>,                 // (row, length in rows)
+    pub tick_counts: Vec<(Row, u32)>,           // (row, ticks per beat)
+    pub combos: Vec<(Row, u32, u32)>,           // (row, hit combo, miss combo)
+    pub labels: Vec<(Row, String)>,             // (row, label)
+    pub time_signatures: Vec<(Row, u32, u32)>,  // (row, numerator, denominator)
+}
+
+impl TimingSegments {
+    pub fn new() -> TimingSegments {
+        TimingSegments {
+            delays: Vec::new(),
+            warps: Vec::new(),
+            speeds: Vec::new(),
+            scrolls: Vec::new(),
+            fakes: Vec::new(),
+            tick_counts: Vec::new(),
+            combos: Vec::new(),
+            labels: Vec::new(),
+            time_signatures: Vec::new(),
+        }
+    }
+}
+
+impl Default for TimingSegments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedSegment {
+    pub row: Row,
+    pub ratio: f64,
+    pub duration: f64,     // Length of the transition to `ratio`
+    pub in_seconds: bool,  // true = duration in seconds, false = in beats
+}
+
+/// Timing of a .ssc chart that overrides the song timing ("split timing").
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartTiming {
+    pub offset: f64,            // #OFFSET in MILLISECONDS, like `SmFile::offset`
+    pub bpms: Vec<(Row, f64)>,  // (row, bpm)
+    pub stops: Vec<(Row, f64)>, // (row, duration in seconds)
+    pub segments: TimingSegments,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Metadata {
     pub title: String,
     pub subtitle: String,
     pub artist: String,
     pub title_translit: String,
+    pub subtitle_translit: String,
     pub artist_translit: String,
+    pub genre: String,
     pub credit: String,
     pub music: String,
     pub banner: String,
     pub background: String,
+    pub lyrics_path: String,
+    pub cd_title: String,
+    pub sample_start: f64,  // #SAMPLESTART in MILLISECONDS
+    pub sample_length: f64, // #SAMPLELENGTH in MILLISECONDS
+    pub selectable: String, // YES, NO, ROULETTE, ES or OMES; empty if absent
 }
 
 impl Metadata {
@@ -40,27 +124,44 @@ impl Metadata {
             subtitle: String::new(),
             artist: String::new(),
             title_translit: String::new(),
+            subtitle_translit: String::new(),
             artist_translit: String::new(),
+            genre: String::new(),
             credit: String::new(),
             music: String::new(),
             banner: String::new(),
             background: String::new(),
+            lyrics_path: String::new(),
+            cd_title: String::new(),
+            sample_start: 0.0,
+            sample_length: 0.0,
+            selectable: String::new(),
         }
     }
-    pub fn parse(&mut self, content: &str) {
-        parse_field(content, r"#TITLE:(.*?);", &mut self.title);
-        parse_field(content, r"#SUBTITLE:(.*?);", &mut self.subtitle);
-        parse_field(content, r"#ARTIST:(.*?);", &mut self.artist);
-        parse_field(content, r"#TITLETRANSLIT:(.*?);", &mut self.title_translit);
-        parse_field(
-            content,
-            r"#ARTISTTRANSLIT:(.*?);",
-            &mut self.artist_translit,
-        );
-        parse_field(content, r"#CREDIT:(.*?);", &mut self.credit);
-        parse_field(content, r"#MUSIC:(.*?);", &mut self.music);
-        parse_field(content, r"#BANNER:(.*?);", &mut self.banner);
-        parse_field(content, r"#BACKGROUND:(.*?);", &mut self.background);
+    pub fn parse(&mut self, tags: &[MsdTag]) -> Result<(), Error> {
+        parse_field(tags, "TITLE", &mut self.title)?;
+        parse_field(tags, "SUBTITLE", &mut self.subtitle)?;
+        parse_field(tags, "ARTIST", &mut self.artist)?;
+        parse_field(tags, "TITLETRANSLIT", &mut self.title_translit)?;
+        parse_field(tags, "SUBTITLETRANSLIT", &mut self.subtitle_translit)?;
+        parse_field(tags, "ARTISTTRANSLIT", &mut self.artist_translit)?;
+        parse_field(tags, "GENRE", &mut self.genre)?;
+        parse_field(tags, "CREDIT", &mut self.credit)?;
+        parse_field(tags, "MUSIC", &mut self.music)?;
+        parse_field(tags, "BANNER", &mut self.banner)?;
+        parse_field(tags, "BACKGROUND", &mut self.background)?;
+        parse_field(tags, "LYRICSPATH", &mut self.lyrics_path)?;
+        parse_field(tags, "CDTITLE", &mut self.cd_title)?;
+        parse_seconds(tags, "SAMPLESTART", &mut self.sample_start)?;
+        parse_seconds(tags, "SAMPLELENGTH", &mut self.sample_length)?;
+        parse_field(tags, "SELECTABLE", &mut self.selectable)?;
+        Ok(())
+    }
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -71,8 +172,14 @@ pub struct Chart {
     pub difficulty: String,
     pub meter: u32,
     pub radar_values: Vec<f64>,
+    pub chart_name: String,  // .ssc only
+    pub chart_style: String, // .ssc only
+    pub credit: String,      // .ssc only
+    pub timing: Option<ChartTiming>, // .ssc split timing, None = uses the song timing
     pub column_count: u32,
     pub measures: Vec<Measure>,
+    pub holds: Vec<HoldNote>,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl Chart {
@@ -83,16 +190,51 @@ impl Chart {
             difficulty: String::new(),
             meter: 0,
             radar_values: Vec::new(),
+            chart_name: String::new(),
+            chart_style: String::new(),
+            credit: String::new(),
+            timing: None,
             column_count: 0,
             measures: Vec::new(),
+            holds: Vec::new(),
+            diagnostics: Vec::new(),
         }
     }
 }
 
+impl Default for Chart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoldNote {
+    pub column: usize,
+    pub start_ms: f64, // Time in MILLISECONDS
+    pub end_ms: f64,   // Time in MILLISECONDS
+    pub is_roll: bool,
+}
+
+/// Problems found while decoding a chart that did not stop the parse.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    /// A hold or roll head with no matching tail in its column.
+    UnterminatedHold { column: usize, start_ms: f64 },
+    /// A tail with no open hold or roll in its column.
+    OrphanTail { column: usize, time_ms: f64 },
+    /// A converted note that no snap reached within tolerance; it was moved
+    /// from `time_ms` to the nearest 192nd no other note is on, at `snapped_ms`.
+    OffSnapNote { column: usize, time_ms: f64, snapped_ms: f64 },
+    /// A converted note whose row and column another note is on (such as a
+    /// tap a few ms from a long note's tail); it was left out.
+    OverlappingNote { column: usize, time_ms: f64 },
+}
+
 #[derive(Debug, Clone)]
 pub struct Measure {
     pub beats: Vec<Beat>,
-    pub start_time: f64, // Time in MILLISECONDS
+    pub start_time: f64, // Time in MILLISECONDS from the start of the audio
 }
 
 impl Measure {
@@ -104,24 +246,509 @@ impl Measure {
     }
 }
 
+impl Default for Measure {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Beat {
-    pub time: f64,        // Time in MILLISECONDS
-    pub notes: Vec<bool>, // true = note, false = empty
+    pub row: Row,             // Exact row of this note line from the start of the chart
+    pub time: f64,            // Time in MILLISECONDS from the start of the audio
+    pub notes: Vec<NoteType>, // One entry per column
+    pub judged: bool,         // False inside warps and #FAKES ranges
 }
 impl Beat {
     pub fn new() -> Beat {
         Beat {
+            row: Row::ZERO,
             time: 0.0,
             notes: Vec::new(),
+            judged: true,
         }
     }
+
+    /// Snap of this note line, `None` if it sits between 192nds.
+    pub fn snap(&self) -> Option<Snap> {
+        self.row.snap()
+    }
+}
+
+impl Default for Beat {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteType {
+    Empty,    // '0'
+    Tap,      // '1'
+    HoldHead, // '2'
+    Tail,     // '3' - ends a hold or a roll
+    RollHead, // '4'
+    Mine,     // 'M'
+    Lift,     // 'L'
+    Fake,     // 'F'
+    Keysound, // 'K' - plays a keysound, never judged
+}
+
+impl NoteType {
+    pub fn from_char(c: char) -> Option<NoteType> {
+        match c {
+            '0' => Some(NoteType::Empty),
+            '1' => Some(NoteType::Tap),
+            '2' => Some(NoteType::HoldHead),
+            '3' => Some(NoteType::Tail),
+            '4' => Some(NoteType::RollHead),
+            'M' => Some(NoteType::Mine),
+            'L' => Some(NoteType::Lift),
+            'F' => Some(NoteType::Fake),
+            'K' => Some(NoteType::Keysound),
+            _ => None,
+        }
+    }
 
-pub struct OsuSettings
-{
-    pub od: f64, 
+    pub fn to_char(self) -> char {
+        match self {
+            NoteType::Empty => '0',
+            NoteType::Tap => '1',
+            NoteType::HoldHead => '2',
+            NoteType::Tail => '3',
+            NoteType::RollHead => '4',
+            NoteType::Mine => 'M',
+            NoteType::Lift => 'L',
+            NoteType::Fake => 'F',
+            NoteType::Keysound => 'K',
+        }
+    }
+
+    /// True for objects the player has to hit: taps, hold/roll heads and lifts.
+    pub fn is_note(self) -> bool {
+        matches!(
+            self,
+            NoteType::Tap | NoteType::HoldHead | NoteType::RollHead | NoteType::Lift
+        )
+    }
+}
+
+/// How `converter::osu` turns a chart into a beatmap. `OsuSettings::new()`
+/// gives the defaults; the builder methods change one setting each:
+///
+/// ```
+/// use rotterna_lib::structs::{LongNoteMode, OsuSettings};
+///
+/// let settings = OsuSettings::new()
+///     .od(8.5)
+///     .version_template("{difficulty} {meter}")
+///     .long_notes(LongNoteMode::HoldsOnly);
+/// assert_eq!(settings.hp, 8.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuSettings {
+    pub od: f64,
     pub hp: f64,
-}
\ No newline at end of file
+    pub creator: Option<String>, // None = the song's #CREDIT
+    /// Difficulty name; `{difficulty}`, `{meter}`, `{description}`,
+    /// `{stepstype}` and `{chart_name}` are replaced by the chart's values.
+    pub version_template: String,
+    pub tags: Vec<String>, // Added after the song's genre and the stepstype
+    pub source: String,
+    pub preview_time: PreviewTime,
+    pub audio_lead_in: i32, // MILLISECONDS
+    pub scroll_speed: ScrollSpeedMode,
+    pub long_notes: LongNoteMode,
+    pub mines: MineMode,
+    pub min_long_note_ms: f64, // Shorter holds and rolls become taps
+    pub key_count: Option<u32>, // None = the chart's column count; notes past it are dropped
+    pub rate: f64, // Music rate, 0.5 to 3.0; other rates append "(1.2x)" to the difficulty name
+    /// AudioFilename; `{music}` is the song's #MUSIC, `{stem}` and
+    /// `{extension}` its name and extension, `{rate}` the rate (as in "1.2").
+    pub audio_filename_template: String,
+    pub min_break_ms: f64, // Shortest gap between notes written as a break period; f64::INFINITY for none
+}
+
+impl OsuSettings {
+    pub fn new() -> OsuSettings {
+        OsuSettings {
+            od: 8.0,
+            hp: 8.0,
+            creator: None,
+            version_template: "{difficulty}".to_string(),
+            tags: vec!["rOtterna".to_string()],
+            source: String::new(),
+            preview_time: PreviewTime::SampleStart,
+            audio_lead_in: 0,
+            scroll_speed: ScrollSpeedMode::Stops,
+            long_notes: LongNoteMode::All,
+            mines: MineMode::Ignore,
+            min_long_note_ms: 0.0,
+            key_count: None,
+            rate: 1.0,
+            audio_filename_template: "{music}".to_string(),
+            min_break_ms: 5000.0,
+        }
+    }
+
+    pub fn od(mut self, od: f64) -> Self {
+        self.od = od;
+        self
+    }
+
+    pub fn hp(mut self, hp: f64) -> Self {
+        self.hp = hp;
+        self
+    }
+
+    pub fn creator(mut self, creator: &str) -> Self {
+        self.creator = Some(creator.to_string());
+        self
+    }
+
+    pub fn version_template(mut self, template: &str) -> Self {
+        self.version_template = template.to_string();
+        self
+    }
+
+    pub fn tags(mut self, tags: &[&str]) -> Self {
+        self.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        self
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.source = source.to_string();
+        self
+    }
+
+    pub fn preview_time(mut self, preview_time: PreviewTime) -> Self {
+        self.preview_time = preview_time;
+        self
+    }
+
+    pub fn audio_lead_in(mut self, lead_in_ms: i32) -> Self {
+        self.audio_lead_in = lead_in_ms;
+        self
+    }
+
+    pub fn scroll_speed(mut self, mode: ScrollSpeedMode) -> Self {
+        self.scroll_speed = mode;
+        self
+    }
+
+    pub fn long_notes(mut self, mode: LongNoteMode) -> Self {
+        self.long_notes = mode;
+        self
+    }
+
+    pub fn mines(mut self, mode: MineMode) -> Self {
+        self.mines = mode;
+        self
+    }
+
+    pub fn min_long_note_ms(mut self, length_ms: f64) -> Self {
+        self.min_long_note_ms = length_ms;
+        self
+    }
+
+    pub fn key_count(mut self, keys: u32) -> Self {
+        self.key_count = Some(keys);
+        self
+    }
+
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn audio_filename_template(mut self, template: &str) -> Self {
+        self.audio_filename_template = template.to_string();
+        self
+    }
+
+    pub fn min_break_ms(mut self, length_ms: f64) -> Self {
+        self.min_break_ms = length_ms;
+        self
+    }
+}
+
+impl Default for OsuSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How `SmFile::to_sm_string_with` writes a song.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SmWriteSettings {
+    /// Write radar values computed from the notes instead of the parsed ones,
+    /// in StepMania 5's layout: all categories, once per player.
+    pub refresh_radar_values: bool,
+}
+
+impl SmWriteSettings {
+    pub fn new() -> SmWriteSettings {
+        SmWriteSettings::default()
+    }
+
+    pub fn refresh_radar_values(mut self, refresh: bool) -> Self {
+        self.refresh_radar_values = refresh;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewTime {
+    SampleStart, // The song's #SAMPLESTART, none if it is 0
+    At(i32),     // MILLISECONDS
+    None,
+}
+
+/// Which scroll speed changes become inherited (SV) timing points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollSpeedMode {
+    None,             // Only BPM changes; notes keep scrolling through stops
+    Stops,            // Slowest SV while stops and delays last
+    StopsAndScrolls,  // Also #SCROLLS ratios, as SV multipliers
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LongNoteMode {
+    All,       // Holds and rolls become long notes
+    HoldsOnly, // Rolls become a single tap on their head
+    None,      // Holds and rolls become taps
+}
+
+/// osu!mania has no mines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MineMode {
+    Ignore, // Mines are dropped
+    Taps,   // Mines become taps, for charts that use them as notes
+}
+
+/// An osu! beatmap, as read from a .osu file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuBeatmap {
+    pub format_version: u32, // N in "osu file format vN"
+    pub general: OsuGeneral,
+    pub editor: OsuEditor,
+    pub metadata: OsuMetadata,
+    pub difficulty: OsuDifficulty,
+    pub events: Vec<OsuEvent>,
+    pub timing_points: Vec<OsuTimingPoint>,
+    pub colours: Vec<(String, [u8; 3])>, // (key such as "Combo1", RGB)
+    pub hit_objects: Vec<OsuHitObject>,
+}
+
+impl OsuBeatmap {
+    pub fn new() -> OsuBeatmap {
+        OsuBeatmap {
+            format_version: 14,
+            general: OsuGeneral::new(),
+            editor: OsuEditor::new(),
+            metadata: OsuMetadata::new(),
+            difficulty: OsuDifficulty::new(),
+            events: Vec::new(),
+            timing_points: Vec::new(),
+            colours: Vec::new(),
+            hit_objects: Vec::new(),
+        }
+    }
+
+    /// Number of osu!mania keys, which mania maps store as the circle size.
+    pub fn key_count(&self) -> u32 {
+        (self.difficulty.circle_size.round() as u32).max(1)
+    }
+}
+
+impl Default for OsuBeatmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuGeneral {
+    pub audio_filename: String,
+    pub audio_lead_in: i32,  // MILLISECONDS of silence before the audio starts
+    pub preview_time: i32,   // MILLISECONDS, -1 = none
+    pub countdown: u32,
+    pub sample_set: String,  // Normal, Soft or Drum
+    pub stack_leniency: f64,
+    pub mode: u32,           // 0 = osu!, 1 = taiko, 2 = catch, 3 = mania
+    pub letterbox_in_breaks: bool,
+    pub special_style: bool, // mania: N+1 layout with the scratch column on the left
+    pub widescreen_storyboard: bool,
+    pub other: Vec<(String, String)>, // Keys not listed above, in file order
+}
+
+impl OsuGeneral {
+    pub fn new() -> OsuGeneral {
+        OsuGeneral {
+            audio_filename: String::new(),
+            audio_lead_in: 0,
+            preview_time: -1,
+            countdown: 1,
+            sample_set: "Normal".to_string(),
+            stack_leniency: 0.7,
+            mode: 0,
+            letterbox_in_breaks: false,
+            special_style: false,
+            widescreen_storyboard: false,
+            other: Vec::new(),
+        }
+    }
+}
+
+impl Default for OsuGeneral {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuEditor {
+    pub bookmarks: Vec<i32>, // MILLISECONDS
+    pub distance_spacing: f64,
+    pub beat_divisor: u32,
+    pub grid_size: u32,
+    pub timeline_zoom: f64,
+    pub other: Vec<(String, String)>, // Keys not listed above, in file order
+}
+
+impl OsuEditor {
+    pub fn new() -> OsuEditor {
+        OsuEditor {
+            bookmarks: Vec::new(),
+            distance_spacing: 1.0,
+            beat_divisor: 4,
+            grid_size: 4,
+            timeline_zoom: 1.0,
+            other: Vec::new(),
+        }
+    }
+}
+
+impl Default for OsuEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuMetadata {
+    pub title: String,
+    pub title_unicode: String,
+    pub artist: String,
+    pub artist_unicode: String,
+    pub creator: String,
+    pub version: String, // Difficulty name
+    pub source: String,
+    pub tags: Vec<String>,
+    pub beatmap_id: i32,
+    pub beatmap_set_id: i32,
+    pub other: Vec<(String, String)>, // Keys not listed above, in file order
+}
+
+impl OsuMetadata {
+    pub fn new() -> OsuMetadata {
+        OsuMetadata {
+            title: String::new(),
+            title_unicode: String::new(),
+            artist: String::new(),
+            artist_unicode: String::new(),
+            creator: String::new(),
+            version: String::new(),
+            source: String::new(),
+            tags: Vec::new(),
+            beatmap_id: 0,
+            beatmap_set_id: -1,
+            other: Vec::new(),
+        }
+    }
+}
+
+impl Default for OsuMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuDifficulty {
+    pub hp_drain_rate: f64,
+    pub circle_size: f64, // Key count in osu!mania
+    pub overall_difficulty: f64,
+    pub approach_rate: f64,
+    pub slider_multiplier: f64,
+    pub slider_tick_rate: f64,
+    pub other: Vec<(String, String)>, // Keys not listed above, in file order
+}
+
+impl OsuDifficulty {
+    pub fn new() -> OsuDifficulty {
+        OsuDifficulty {
+            hp_drain_rate: 5.0,
+            circle_size: 5.0,
+            overall_difficulty: 5.0,
+            approach_rate: 5.0,
+            slider_multiplier: 1.4,
+            slider_tick_rate: 1.0,
+            other: Vec::new(),
+        }
+    }
+}
+
+impl Default for OsuDifficulty {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OsuEvent {
+    Background { filename: String, x: i32, y: i32 },
+    Video { start_time: i32, filename: String, x: i32, y: i32 },
+    Break { start_time: i32, end_time: i32 }, // MILLISECONDS
+    Other(String), // Storyboard and other event lines, as written
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuTimingPoint {
+    pub time: f64,        // MILLISECONDS
+    pub beat_length: f64, // ms per beat if uninherited, else -100 / slider velocity
+    pub meter: u32,
+    pub sample_set: u32,
+    pub sample_index: u32,
+    pub volume: u32,
+    pub uninherited: bool,
+    pub effects: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsuHitObject {
+    pub x: i32,
+    pub y: i32,
+    pub time: i32,        // MILLISECONDS
+    pub object_type: u32, // Type bits as written, including new combo flags
+    pub hit_sound: u32,
+    pub kind: OsuHitObjectKind,
+    pub hit_sample: String, // As written, empty if absent
+}
+
+impl OsuHitObject {
+    /// osu!mania column of this object for `key_count` keys.
+    pub fn column(&self, key_count: u32) -> usize {
+        let column = (self.x.max(0) as f64 * key_count as f64 / 512.0).floor() as usize;
+        column.min(key_count.saturating_sub(1) as usize)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OsuHitObjectKind {
+    Circle,
+    Slider { params: String }, // curve,slides,length[,edge sounds,edge sets] as written
+    Spinner { end_time: i32 },
+    Hold { end_time: i32 }, // osu!mania long note
+}
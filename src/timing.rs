@@ -0,0 +1,577 @@
+use crate::structs::{Chart, SmFile, TimingSegments};
+
+// StepMania row system constants
+pub const ROWS_PER_BEAT: f64 = 48.0; // 1 beat = 48 rows (for 4/4 time)
+pub const ROWS_PER_MEASURE: f64 = 192.0; // 1 measure = 192 rows (4 beats * 48)
+
+/// An exact position in StepMania rows, kept as a reduced fraction so note
+/// lines of any measure density (e.g. 7 lines per measure) and decimal beats
+/// from timing tags are stored without rounding. Times are only computed from
+/// rows at the end, by `TimingData`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Row {
+    num: i64,
+    den: i64, // Always > 0, coprime with `num`
+}
+
+impl Row {
+    pub const ZERO: Row = Row { num: 0, den: 1 };
+
+    /// `num / den` rows. A zero `den` gives row 0.
+    pub fn new(num: i64, den: i64) -> Row {
+        Row::reduce(num as i128, den as i128)
+    }
+
+    pub fn from_rows(rows: i64) -> Row {
+        Row { num: rows, den: 1 }
+    }
+
+    /// Row of note line `line` out of `lines` in measure `measure`.
+    pub fn in_measure(measure: usize, line: usize, lines: usize) -> Row {
+        let rows_per_measure = ROWS_PER_MEASURE as i128;
+        let lines = lines.max(1) as i128;
+        Row::reduce(
+            measure as i128 * rows_per_measure * lines + line as i128 * rows_per_measure,
+            lines,
+        )
+    }
+
+    /// Nearest whole row to `rows`, as StepMania rounds beats to rows.
+    pub fn round(rows: f64) -> Row {
+        Row::from_rows(rows.round() as i64)
+    }
+
+    /// Nearest thousandth of a row to `rows`, for positions computed from
+    /// times (such as the end of a negative BPM warp) that are never exact.
+    pub fn approximate(rows: f64) -> Row {
+        Row::new((rows * 1000.0).round() as i64, 1000)
+    }
+
+    /// Parses a beat as written in timing tags (`12.500`, `-1`, `.25`) into an
+    /// exact row. Other float syntaxes are rounded to the nearest row.
+    ///
+    /// Beats on whole rows rarely have an exact decimal (a 12th is 0.333...),
+    /// so a beat within half its last written digit of a whole row is read as
+    /// that row, as StepMania rounds beats to rows.
+    pub fn parse_beat(text: &str) -> Option<Row> {
+        let text = text.trim();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() && fraction.is_empty()
+            || !is_digits(whole)
+            || !is_digits(fraction)
+            || whole.len() > 12
+        {
+            return text.parse::<f64>().ok().map(|beats| Row::round(beats * ROWS_PER_BEAT));
+        }
+
+        // Digits past the ninth decimal are far below a row and are dropped
+        let fraction = &fraction[..fraction.len().min(9)];
+        let den = 10i128.pow(fraction.len() as u32);
+        let whole: i128 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+        let fraction: i128 = if fraction.is_empty() { 0 } else { fraction.parse().ok()? };
+        let num = (whole * den + fraction) * ROWS_PER_BEAT as i128;
+        let num = if negative { -num } else { num };
+
+        // Half the last digit is (ROWS_PER_BEAT / 2) / den rows
+        let nearest = (2 * num + den).div_euclid(2 * den);
+        if (num - nearest * den).abs() * 2 <= ROWS_PER_BEAT as i128 {
+            return Some(Row::reduce(nearest, 1));
+        }
+        Some(Row::reduce(num, den))
+    }
+
+    pub fn numer(self) -> i64 {
+        self.num
+    }
+
+    pub fn denom(self) -> i64 {
+        self.den
+    }
+
+    pub fn is_whole(self) -> bool {
+        self.den == 1
+    }
+
+    /// Rows as a float, for time computations.
+    pub fn as_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn beats(self) -> f64 {
+        self.as_f64() / ROWS_PER_BEAT
+    }
+
+    /// Index of the measure containing this row (rows before 0 give negative measures).
+    pub fn measure(self) -> i64 {
+        (self.num as i128 * self.den.signum() as i128)
+            .div_euclid(self.den as i128 * ROWS_PER_MEASURE as i128) as i64
+    }
+
+    /// Snap of this row from its position in its measure, or `None` for rows
+    /// between 192nds (e.g. in a 7-line measure).
+    pub fn snap(self) -> Option<Snap> {
+        if !self.is_whole() {
+            return None;
+        }
+        let in_measure = self.num.rem_euclid(ROWS_PER_MEASURE as i64);
+        Snap::ALL
+            .into_iter()
+            .find(|snap| in_measure % snap.rows() as i64 == 0)
+    }
+
+    fn reduce(num: i128, den: i128) -> Row {
+        if den == 0 {
+            return Row::ZERO;
+        }
+        let divisor = gcd(num, den) * den.signum();
+        let (num, den) = (num / divisor, den / divisor);
+        match (i64::try_from(num), i64::try_from(den)) {
+            (Ok(num), Ok(den)) => Row { num, den },
+            // Past i64 precision, fall back to the nearest whole row
+            _ => Row::round(num as f64 / den as f64),
+        }
+    }
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs().max(1)
+}
+
+impl std::ops::Add for Row {
+    type Output = Row;
+    fn add(self, other: Row) -> Row {
+        Row::reduce(
+            self.num as i128 * other.den as i128 + other.num as i128 * self.den as i128,
+            self.den as i128 * other.den as i128,
+        )
+    }
+}
+
+impl std::ops::Sub for Row {
+    type Output = Row;
+    fn sub(self, other: Row) -> Row {
+        Row::reduce(
+            self.num as i128 * other.den as i128 - other.num as i128 * self.den as i128,
+            self.den as i128 * other.den as i128,
+        )
+    }
+}
+
+impl Ord for Row {
+    fn cmp(&self, other: &Row) -> std::cmp::Ordering {
+        (self.num as i128 * other.den as i128).cmp(&(other.num as i128 * self.den as i128))
+    }
+}
+
+impl PartialOrd for Row {
+    fn partial_cmp(&self, other: &Row) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Default for Row {
+    fn default() -> Self {
+        Row::ZERO
+    }
+}
+
+impl std::fmt::Display for Row {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// Finest note division a row falls on, as StepMania colors notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Snap {
+    Fourth,
+    Eighth,
+    Twelfth,
+    Sixteenth,
+    TwentyFourth,
+    ThirtySecond,
+    FortyEighth,
+    SixtyFourth,
+    HundredNinetySecond, // Any other whole row
+}
+
+impl Snap {
+    /// All snaps, coarsest first.
+    pub const ALL: [Snap; 9] = [
+        Snap::Fourth,
+        Snap::Eighth,
+        Snap::Twelfth,
+        Snap::Sixteenth,
+        Snap::TwentyFourth,
+        Snap::ThirtySecond,
+        Snap::FortyEighth,
+        Snap::SixtyFourth,
+        Snap::HundredNinetySecond,
+    ];
+
+    /// Note lines per measure at this snap (4 for 4ths, 192 for 192nds).
+    pub fn per_measure(self) -> u32 {
+        match self {
+            Snap::Fourth => 4,
+            Snap::Eighth => 8,
+            Snap::Twelfth => 12,
+            Snap::Sixteenth => 16,
+            Snap::TwentyFourth => 24,
+            Snap::ThirtySecond => 32,
+            Snap::FortyEighth => 48,
+            Snap::SixtyFourth => 64,
+            Snap::HundredNinetySecond => 192,
+        }
+    }
+
+    /// Rows between two lines at this snap.
+    pub fn rows(self) -> u32 {
+        ROWS_PER_MEASURE as u32 / self.per_measure()
+    }
+}
+
+/// A stretch of constant BPM starting at `row`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingSegment {
+    pub row: Row,
+    pub time_ms: f64,  // Time in MILLISECONDS from the start of the audio when `row` is reached
+    pub bpm: f64,      // BPM from `row` until the next segment
+    pub delay_ms: f64, // #DELAYS pause before the notes on `row`, 0 if none
+    pub stop_ms: f64,  // #STOPS pause after the notes on `row`, 0 if none
+    pub warped: bool,  // Rows from `row` until the next segment are skipped instantly
+}
+
+/// Row <-> time conversion for one timing source (a song, or a .ssc chart
+/// with split timing). Times are positions in the audio file, so row 0 sits
+/// at `-offset`.
+///
+/// A delay at row `r` pauses before the notes on `r` are hit, a stop pauses
+/// after them, as in StepMania. Warps skip rows without advancing time;
+/// negative BPMs and negative stops are turned into warps the way StepMania
+/// loads them, lasting until the time they rewind has been played again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingData {
+    offset: f64, // #OFFSET in MILLISECONDS
+    segments: Vec<TimingSegment>,
+    warps: Vec<RowRange>, // Merged and sorted
+    fakes: Vec<RowRange>,
+}
+
+// (start row, end row), an end of `None` never ends
+type RowRange = (Row, Option<Row>);
+
+// Timing events on one row, before segments are built
+#[derive(Debug, Clone)]
+struct TimingPoint {
+    row: Row,
+    bpm: Option<f64>,
+    delay_ms: f64,
+    stop_ms: f64,
+}
+
+impl TimingData {
+    /// `bpms` and `stops` are (row, value) pairs sorted by row, as stored on
+    /// `SmFile`; `segments` supplies delays, warps and fakes. BPMs must be
+    /// finite and non-zero, as the decoders check.
+    pub fn new(
+        offset_ms: f64,
+        bpms: &[(Row, f64)],
+        stops: &[(Row, f64)],
+        segments: &TimingSegments,
+    ) -> TimingData {
+        let mut points = collect_points(bpms, stops, &segments.delays);
+        let first_bpm = bpms.first().map_or(120.0, |&(_, bpm)| bpm);
+
+        let mut warps: Vec<RowRange> = segments
+            .warps
+            .iter()
+            .filter(|&&(_, length)| length > Row::ZERO)
+            .map(|&(row, length)| (row, Some(row + length)))
+            .collect();
+        warps.extend(fold_negative_time(&mut points, first_bpm));
+        let warps = merge_ranges(warps);
+
+        let fakes = segments
+            .fakes
+            .iter()
+            .filter(|&&(_, length)| length > Row::ZERO)
+            .map(|&(row, length)| (row, Some(row + length)))
+            .collect();
+
+        // Warp edges split segments too, so each segment is either warped or not
+        let mut rows: Vec<Row> = points.iter().map(|p| p.row).collect();
+        for &(start, end) in &warps {
+            rows.push(start);
+            rows.extend(end);
+        }
+        rows.push(Row::ZERO);
+        rows.sort();
+        rows.dedup();
+
+        let mut built: Vec<TimingSegment> = Vec::with_capacity(rows.len());
+        let mut point_idx = 0;
+        let mut bpm = first_bpm;
+        for row in rows {
+            let (mut delay_ms, mut stop_ms) = (0.0, 0.0);
+            if let Some(point) = points.get(point_idx).filter(|p| p.row == row) {
+                if let Some(new_bpm) = point.bpm {
+                    bpm = new_bpm;
+                }
+                delay_ms = point.delay_ms;
+                stop_ms = point.stop_ms;
+                point_idx += 1;
+            }
+
+            let time_ms = match built.last() {
+                Some(previous) => previous.leave_time(row),
+                None => -offset_ms,
+            };
+            built.push(TimingSegment {
+                row,
+                time_ms,
+                bpm,
+                delay_ms,
+                stop_ms,
+                warped: in_ranges(&warps, row),
+            });
+        }
+
+        TimingData {
+            offset: offset_ms,
+            segments: built,
+            warps,
+            fakes,
+        }
+    }
+
+    pub fn from_sm(sm: &SmFile) -> TimingData {
+        TimingData::new(sm.offset, &sm.bpms, &sm.stops, &sm.segments)
+    }
+
+    /// Timing of `chart`: its own .ssc split timing if it has one, the song's otherwise.
+    pub fn for_chart(sm: &SmFile, chart: &Chart) -> TimingData {
+        match &chart.timing {
+            Some(timing) => {
+                TimingData::new(timing.offset, &timing.bpms, &timing.stops, &timing.segments)
+            }
+            None => TimingData::from_sm(sm),
+        }
+    }
+
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    /// Segments in row order; the first always starts at row 0.
+    pub fn segments(&self) -> &[TimingSegment] {
+        &self.segments
+    }
+
+    /// Warped row ranges as (start, end), including those made from negative
+    /// BPMs and stops. `end` is `None` if the rewound time is never recovered.
+    pub fn warps(&self) -> &[(Row, Option<Row>)] {
+        &self.warps
+    }
+
+    /// Time in MILLISECONDS at which notes on `row` are hit.
+    pub fn time_at_row(&self, row: Row) -> f64 {
+        let segment = self.segment_at_row(row);
+        if row < segment.row {
+            // Rows before the first segment extrapolate backwards at its BPM
+            return segment.time_ms - (segment.row - row).as_f64() * segment.ms_per_row();
+        }
+        if row == segment.row {
+            return segment.time_ms + segment.delay_ms;
+        }
+        segment.leave_time(row)
+    }
+
+    /// Row reached at `time_ms`, in rows as a float since times rarely land on
+    /// an exact row; during a pause, the row of the pause.
+    pub fn row_at_time(&self, time_ms: f64) -> f64 {
+        let idx = self
+            .segments
+            .partition_point(|s| s.time_ms <= time_ms)
+            .saturating_sub(1);
+        let segment = &self.segments[idx];
+
+        if time_ms < segment.time_ms {
+            // Times before the first segment extrapolate backwards at its BPM
+            return segment.row.as_f64() - (segment.time_ms - time_ms) / segment.ms_per_row();
+        }
+        let moving_from = segment.time_ms + segment.delay_ms + segment.stop_ms;
+        if time_ms <= moving_from || segment.warped {
+            return segment.row.as_f64();
+        }
+        segment.row.as_f64() + (time_ms - moving_from) / segment.ms_per_row()
+    }
+
+    pub fn bpm_at_row(&self, row: Row) -> f64 {
+        self.segment_at_row(row).bpm
+    }
+
+    /// True if `row` is inside a warp. As in StepMania, a row carrying a stop
+    /// or a delay is never warped, so stops can be chained between warps.
+    pub fn is_warp_at_row(&self, row: Row) -> bool {
+        if !in_ranges(&self.warps, row) {
+            return false;
+        }
+        let segment = self.segment_at_row(row);
+        !(segment.row == row && (segment.stop_ms != 0.0 || segment.delay_ms != 0.0))
+    }
+
+    /// False for rows inside a warp or a #FAKES range: notes there are shown
+    /// but never judged.
+    pub fn is_judged_at_row(&self, row: Row) -> bool {
+        !self.is_warp_at_row(row) && !in_ranges(&self.fakes, row)
+    }
+
+    fn segment_at_row(&self, row: Row) -> &TimingSegment {
+        let idx = self.segments.partition_point(|s| s.row <= row).saturating_sub(1);
+        &self.segments[idx]
+    }
+}
+
+impl TimingSegment {
+    fn ms_per_row(&self) -> f64 {
+        60000.0 / self.bpm.abs() / ROWS_PER_BEAT
+    }
+
+    /// Time at which `row` (> self.row, within this segment) is reached.
+    fn leave_time(&self, row: Row) -> f64 {
+        let moving = if self.warped { 0.0 } else { (row - self.row).as_f64() * self.ms_per_row() };
+        self.time_ms + self.delay_ms + self.stop_ms + moving
+    }
+}
+
+/// Merges BPM changes, stops and delays into one point per row, sorted.
+fn collect_points(
+    bpms: &[(Row, f64)],
+    stops: &[(Row, f64)],
+    delays: &[(Row, f64)],
+) -> Vec<TimingPoint> {
+    let mut points: Vec<TimingPoint> = Vec::new();
+    let events = bpms
+        .iter()
+        .map(|&(row, bpm)| (row, Some(bpm), 0.0, 0.0))
+        .chain(stops.iter().map(|&(row, seconds)| (row, None, 0.0, seconds * 1000.0)))
+        .chain(delays.iter().map(|&(row, seconds)| (row, None, seconds * 1000.0, 0.0)));
+
+    for (row, bpm, delay_ms, stop_ms) in events {
+        // Events before row 0 are folded into row 0
+        let row = row.max(Row::ZERO);
+        match points.iter_mut().find(|p| p.row == row) {
+            Some(point) => {
+                point.bpm = bpm.or(point.bpm);
+                point.delay_ms += delay_ms;
+                point.stop_ms += stop_ms;
+            }
+            None => points.push(TimingPoint {
+                row,
+                bpm,
+                delay_ms,
+                stop_ms,
+            }),
+        }
+    }
+    points.sort_by_key(|p| p.row);
+    points
+}
+
+/// Turns negative BPMs and negative pauses into warps. The time a negative
+/// stretch rewinds is a debt; rows stay warped until positive BPMs and pauses
+/// have paid it back. Pauses spent on the debt are removed from `points`.
+fn fold_negative_time(points: &mut [TimingPoint], first_bpm: f64) -> Vec<RowRange> {
+    let mut warps = Vec::new();
+    let mut debt_ms = 0.0;
+    let mut warp_start: Option<Row> = None;
+    let mut bpm = first_bpm;
+    let mut row = Row::ZERO;
+
+    // Pays `amount` towards the debt, returning what is left over
+    fn repay(debt_ms: &mut f64, amount: f64) -> f64 {
+        let paid = amount.min(*debt_ms);
+        *debt_ms -= paid;
+        amount - paid
+    }
+
+    for idx in 0..=points.len() {
+        // Progress from the previous row to this point (or forever after the last)
+        let rows_to_next = points.get(idx).map_or(f64::INFINITY, |p| (p.row - row).as_f64());
+        let ms_per_row = 60000.0 / bpm.abs() / ROWS_PER_BEAT;
+        if bpm < 0.0 {
+            if debt_ms == 0.0 {
+                warp_start = Some(row);
+            }
+            debt_ms += rows_to_next * ms_per_row;
+        } else if debt_ms > 0.0 {
+            let rows_to_repay = debt_ms / ms_per_row;
+            if rows_to_repay <= rows_to_next {
+                debt_ms = 0.0;
+                warps.push((warp_start.take().unwrap(), Some(row + Row::approximate(rows_to_repay))));
+            } else {
+                debt_ms -= rows_to_next * ms_per_row;
+            }
+        }
+
+        let Some(point) = points.get_mut(idx) else {
+            break;
+        };
+        row = point.row;
+        if let Some(new_bpm) = point.bpm {
+            bpm = new_bpm;
+        }
+
+        for pause in [&mut point.delay_ms, &mut point.stop_ms] {
+            if *pause < 0.0 {
+                if debt_ms == 0.0 {
+                    warp_start = Some(row);
+                }
+                debt_ms += -*pause;
+                *pause = 0.0;
+            } else if debt_ms > 0.0 {
+                *pause = repay(&mut debt_ms, *pause);
+                if debt_ms == 0.0 {
+                    warps.push((warp_start.take().unwrap(), Some(row)));
+                }
+            }
+        }
+    }
+
+    if let Some(start) = warp_start {
+        warps.push((start, None));
+    }
+    warps
+}
+
+fn merge_ranges(mut ranges: Vec<RowRange>) -> Vec<RowRange> {
+    ranges.sort_by_key(|range| range.0);
+    let mut merged: Vec<RowRange> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if last.1.is_none_or(|last_end| start <= last_end) => {
+                last.1 = last.1.zip(end).map(|(a, b)| a.max(b));
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn in_ranges(ranges: &[RowRange], row: Row) -> bool {
+    ranges
+        .iter()
+        .any(|&(start, end)| start <= row && end.is_none_or(|end| row < end))
+}
@@ -1,38 +1,109 @@
-use regex::Regex;
+use crate::decoding::msd::{MsdTag, find_tag};
+use crate::error::Error;
 use std::str::FromStr;
-pub fn parse_field<T: FromStr>(content: &str, pattern: &str, field: &mut T) {
-    let re = Regex::new(pattern).unwrap();
-    if let Some(cap) = re.captures(content) {
-        if let Ok(value) = cap[1].trim().parse::<T>() {
-            *field = value;
+
+/// Debug trace sent to the `log` facade when the `log` feature is enabled,
+/// and compiled out otherwise.
+macro_rules! log_debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    }};
+}
+pub(crate) use log_debug;
+
+/// Parses the value of `#TAG:value;` into `field`, leaving it untouched if the
+/// tag is absent or empty. Extra ':'-separated params are joined back, so
+/// values such as titles may contain colons.
+pub fn parse_field<T: FromStr>(tags: &[MsdTag], tag: &str, field: &mut T) -> Result<(), Error> {
+    let Some(found) = find_tag(tags, tag) else {
+        return Ok(());
+    };
+    let Some(param) = found.params.first() else {
+        return Ok(());
+    };
+    let joined = found
+        .params
+        .iter()
+        .map(|p| p.value.as_str())
+        .collect::<Vec<_>>()
+        .join(":");
+    let text = joined.trim();
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    match text.parse::<T>() {
+        Ok(parsed) => {
+            *field = parsed;
+            Ok(())
+        }
+        Err(_) => {
+            let leading = param.value.len() - param.value.trim_start().len();
+            let (line, column) = param.position_at(leading);
+            Err(Error::MalformedValue {
+                tag: tag.to_string(),
+                text: text.to_string(),
+                line,
+                column,
+            })
         }
     }
 }
 
-pub fn parse_pairs(content: &str, pattern: &str, list: &mut Vec<(f64, f64)>) {
-    let re = Regex::new(pattern).unwrap();
+/// Parses a value in seconds, such as #OFFSET, into `milliseconds`.
+pub fn parse_seconds(tags: &[MsdTag], tag: &str, milliseconds: &mut f64) -> Result<(), Error> {
+    let mut seconds = *milliseconds / 1000.0;
+    parse_field(tags, tag, &mut seconds)?;
+    *milliseconds = seconds * 1000.0;
+    Ok(())
+}
 
-    if let Some(cap) = re.captures(content) {
-        let raw_str = cap[1].trim();
-        // Nettoyage des sauts de ligne et espaces
-        let clean_str = raw_str.replace(&['\n', '\r', ' '][..], "");
+/// Parses `#TAG:a=b=c,a=b=c,...;` with `parse`, which receives the trimmed
+/// '='-separated fields of one entry and returns `None` if they are malformed.
+/// Returns `None` if the tag is absent.
+pub fn parse_list<T>(
+    tags: &[MsdTag],
+    tag: &str,
+    parse: impl Fn(&[&str]) -> Option<T>,
+) -> Result<Option<Vec<T>>, Error> {
+    let Some(found) = find_tag(tags, tag) else {
+        log_debug!("[parse_list] No match found for tag: #{}", tag);
+        return Ok(None);
+    };
+    let mut list = Vec::new();
+    let Some(param) = found.params.first() else {
+        return Ok(Some(list));
+    };
+    log_debug!("[parse_list] Raw: '{}'", param.value.trim());
 
-        println!("[parse_pairs] Raw: '{}' -> Clean: '{}'", raw_str, clean_str);
+    let mut pos = 0;
+    for piece in param.value.split(',') {
+        let piece_start = pos + (piece.len() - piece.trim_start().len());
+        pos += piece.len() + 1;
 
-        for pair in clean_str.split(',') {
-            if pair.is_empty() {
-                continue;
-            }
+        let entry = piece.trim();
+        if entry.is_empty() {
+            continue;
+        }
 
-            // On tente de diviser sur '=' et de parser les deux côtés en f64
-            if let Some((k, v)) = pair.split_once('=') {
-                match (k.parse::<f64>(), v.parse::<f64>()) {
-                    (Ok(key), Ok(val)) => list.push((key, val)),
-                    _ => println!("[parse_pairs] Failed to parse pair: {}", pair),
-                }
+        let fields: Vec<&str> = entry.split('=').map(str::trim).collect();
+        match parse(&fields) {
+            Some(value) => list.push(value),
+            None => {
+                let (line, column) = param.position_at(piece_start);
+                return Err(Error::MalformedPair {
+                    tag: tag.to_string(),
+                    text: entry.to_string(),
+                    line,
+                    column,
+                });
             }
         }
-    } else {
-        println!("[parse_pairs] No match found for pattern: {}", pattern);
     }
+    Ok(Some(list))
 }
@@ -0,0 +1,182 @@
+use rotterna_lib::structs::SmFile;
+use std::path::PathBuf;
+
+/// A 4-key song at 150 BPM whose chart is `lines` as 16th notes.
+fn chart_of(lines: &[&str]) -> SmFile {
+    let measures: Vec<String> = lines
+        .chunks(16)
+        .map(|measure| {
+            let mut measure = measure.to_vec();
+            measure.resize(16, "0000");
+            measure.join("\n")
+        })
+        .collect();
+    let notes = measures.join("\n,\n");
+    SmFile::from_string(&format!("#BPMS:0=150;#NOTES:dance-single::Hard:10::\n{}\n;", notes)).unwrap()
+}
+
+#[test]
+fn counts_chords_and_their_peak() {
+    // 16ths at 150 BPM are 100ms apart: 10 rows per second
+    let sm = chart_of(&[
+        "1100", "0010", "1001", "0100", "1110", "0001", "1111", "0000",
+        "0000", "0000", "0000", "0000", "0000", "0000", "0000", "0000",
+        "0110",
+    ]);
+    let stats = sm.charts[0].pattern_stats();
+    assert_eq!((stats.notes, stats.rows), (16, 8));
+    assert_eq!((stats.jumps.count, stats.jumps.peak_per_second), (3, 2));
+    assert_eq!((stats.hands.count, stats.hands.peak_per_second), (1, 1));
+    assert_eq!((stats.quads.count, stats.quads.peak_per_second), (1, 1));
+}
+
+#[test]
+fn counts_jacks_per_column() {
+    let sm = chart_of(&[
+        "1000", "1000", "0100", "0010", "0010", "0010", "0001", "1001",
+        "0000", "0000", "0000", "0000", "0000", "0000", "0000", "0000",
+        // Too far from the previous hit to jack it
+        "1000",
+    ]);
+    let stats = sm.charts[0].pattern_stats();
+    assert_eq!(stats.minijacks, vec![1, 0, 0, 1]);
+    assert_eq!(stats.longjacks, vec![0, 0, 1, 0]);
+}
+
+#[test]
+fn finds_trills_rolls_and_jumptrills() {
+    let sm = chart_of(&[
+        // One-hand trill, then a roll each way
+        "1000", "0100", "1000", "0100", "0000", "0000", "1000", "0100",
+        "0010", "0001", "0010", "0100", "1000", "0000", "0000", "0000",
+        // Jumptrill
+        "1100", "0011", "1100", "0011", "1100",
+    ]);
+    let stats = sm.charts[0].pattern_stats();
+    assert_eq!(stats.one_hand_trills, 1);
+    assert_eq!(stats.rolls, 2);
+    assert_eq!(stats.jumptrills, 1);
+}
+
+#[test]
+fn finds_streams() {
+    let stream = ["1000", "0010", "0100", "0001"];
+    let mut lines: Vec<&str> = stream.iter().cycle().take(32).copied().collect();
+    lines.extend(["0000"; 16]);
+    lines.extend(stream.iter().cycle().take(8));
+    let sm = chart_of(&lines);
+    let stats = sm.charts[0].pattern_stats();
+
+    assert_eq!(stats.streams.len(), 1);
+    let stream = &stats.streams[0];
+    assert_eq!((stream.rows, stream.notes), (32, 32));
+    assert!((stream.end_ms - stream.start_ms - 3100.0).abs() < 1e-6);
+    assert!((stream.nps - 10.0).abs() < 1e-6);
+}
+
+#[test]
+fn analyses_bundled_charts() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    for name in ["MEGALOVANIA.sm", "Metro.sm", "Turbocharger.sm", "ZanderTwo.sm"] {
+        let sm = SmFile::from_file(dir.join(name)).unwrap();
+        for chart in &sm.charts {
+            let stats = chart.pattern_stats();
+            let chords = stats.jumps.count + stats.hands.count + stats.quads.count;
+            assert!(chords <= stats.rows, "{}", name);
+            assert!(stats.notes >= stats.rows + chords, "{}", name);
+            assert_eq!(stats.minijacks.len(), 4, "{}", name);
+            for stream in &stats.streams {
+                assert!(stream.rows >= 16 && stream.end_ms > stream.start_ms, "{}", name);
+            }
+        }
+    }
+    let sm = SmFile::from_file(dir.join("MEGALOVANIA.sm")).unwrap();
+    assert!(!sm.charts[0].pattern_stats().streams.is_empty());
+}
+
+#[test]
+fn samples_notes_per_second() {
+    // Two measures of 16ths (10 notes per second), two empty ones, then a jump
+    let stream = ["1000", "0010", "0100", "0001"];
+    let mut lines: Vec<&str> = stream.iter().cycle().take(32).copied().collect();
+    lines.extend(["0000"; 32]);
+    lines.push("1001");
+    let sm = chart_of(&lines);
+    let graph = sm.charts[0].density_graph(1000.0, 500.0);
+
+    // From the first note (0ms) to the jump (6400ms)
+    assert_eq!(graph.samples.len(), 13);
+    assert_eq!(graph.samples[0].time_ms, 0.0);
+    assert_eq!(graph.samples[0].nps, 5.0); // Half the window is before the first note
+    assert_eq!(graph.samples[2].nps, 10.0);
+    assert_eq!(graph.samples[10].nps, 0.0);
+    assert_eq!(graph.peak_nps, 10.0);
+    assert!((graph.average_nps - 34.0 / 6.4).abs() < 1e-9);
+
+    let breaks = sm.charts[0].breaks(3000.0);
+    assert_eq!(breaks.len(), 1);
+    assert!((breaks[0].start_ms - 3100.0).abs() < 1e-9 && (breaks[0].end_ms - 6400.0).abs() < 1e-9);
+    assert!(sm.charts[0].breaks(4000.0).is_empty());
+}
+
+#[test]
+fn holds_are_not_breaks() {
+    let mut lines = vec!["2000"];
+    lines.extend(["0000"; 47]);
+    lines.push("3000");
+    lines.extend(["0000"; 15]);
+    lines.push("0100");
+    let sm = chart_of(&lines);
+    // The hold lasts 4.8s, the next note is 1.6s after its end
+    let breaks = sm.charts[0].breaks(1000.0);
+    assert_eq!(breaks.len(), 1);
+    assert!((breaks[0].start_ms - 4800.0).abs() < 1e-9 && (breaks[0].end_ms - 6400.0).abs() < 1e-9);
+}
+
+#[test]
+fn averages_over_playable_time() {
+    // Two measures of 16ths, 6.5s of nothing, then a jump: the break is left out
+    let stream = ["1000", "0010", "0100", "0001"];
+    let mut lines: Vec<&str> = stream.iter().cycle().take(32).copied().collect();
+    lines.extend(["0000"; 64]);
+    lines.push("1001");
+    let sm = chart_of(&lines);
+    let graph = sm.charts[0].density_graph(1000.0, 500.0);
+    assert!((graph.average_nps - 34.0 / 3.1).abs() < 1e-9, "{}", graph.average_nps);
+
+    // Steps under 1ms sample every 1ms
+    let graph = sm.charts[0].density_graph(1000.0, 1e-9);
+    assert_eq!(graph.samples.len(), 9601);
+}
+
+#[test]
+fn streams_without_length_have_no_nps() {
+    // Far enough into the song that the rows' times round to the same one
+    let measure = ["1000", "0010", "0100", "0001"].repeat(4).join("\n");
+    let sm = SmFile::from_string(&format!(
+        "#OFFSET:-10000000000000;#BPMS:0=1000000000;#NOTES:dance-single::Hard:10::\n{}\n;",
+        measure
+    ))
+    .unwrap();
+    let streams = sm.charts[0].pattern_stats().streams;
+    assert_eq!(streams.len(), 1);
+    assert_eq!((streams[0].start_ms, streams[0].rows), (streams[0].end_ms, 16));
+    assert_eq!(streams[0].nps, 0.0);
+}
+
+#[test]
+fn long_charts_have_bounded_samples() {
+    // A stop of 11.5 days between the two measures
+    let measure = ["1000", "0010", "0100", "0001"].repeat(4).join("\n");
+    let sm = SmFile::from_string(&format!(
+        "#BPMS:0=150;#STOPS:4=1000000;#NOTES:dance-single::Hard:10::\n{}\n,\n{}\n;",
+        measure, measure
+    ))
+    .unwrap();
+    let graph = sm.charts[0].density_graph(1000.0, 1.0);
+    assert!(graph.samples.len() <= 100_000, "{}", graph.samples.len());
+    let span = graph.samples[graph.samples.len() - 1].time_ms - graph.samples[0].time_ms;
+    assert!((span - 1_000_003_100.0).abs() < 1_000_003_100.0 / 99_999.0, "{}", span);
+    // The stop, and the 16th after it, are a break, not playable time
+    assert!((graph.average_nps - 32.0 / 3.0).abs() < 1e-6, "{}", graph.average_nps);
+}
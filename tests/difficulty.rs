@@ -0,0 +1,163 @@
+use rotterna_lib::Error;
+use rotterna_lib::difficulty::Skillset;
+use rotterna_lib::structs::SmFile;
+use std::path::PathBuf;
+
+/// A 4-key song at `bpm` whose chart repeats `pattern` (16th note lines) for 32 measures.
+fn repeated(bpm: u32, pattern: &[&str]) -> SmFile {
+    let measure: Vec<&str> = pattern.iter().cycle().take(16).copied().collect();
+    let notes = vec![measure.join("\n"); 32].join("\n,\n");
+    SmFile::from_string(&format!("#BPMS:0={};#NOTES:dance-single::Hard:10::\n{}\n;", bpm, notes)).unwrap()
+}
+
+#[test]
+fn rates_patterns_in_their_skillset() {
+    let cases = [
+        (Skillset::Stream, repeated(180, &["1000", "0010", "0100", "0001"])),
+        (Skillset::Jumpstream, repeated(170, &["1010", "0100", "1001", "0010", "0101", "1000", "0110", "0001"])),
+        (Skillset::Handstream, repeated(150, &["1101", "0010", "1011", "0100", "0111", "1000", "1110", "0001"])),
+        (Skillset::JackSpeed, repeated(180, &["1000", "0000", "1000", "0000"])),
+        (Skillset::Chordjack, repeated(120, &["1101", "0000", "1011", "0000", "0111", "0000", "1110", "0000"])),
+    ];
+    for (skillset, sm) in cases {
+        let rating = sm.charts[0].difficulty(1.0).unwrap();
+        assert_eq!(rating.main_skillset(), skillset, "{:?}", rating);
+        assert_eq!(rating.overall, rating.skillset(skillset));
+    }
+}
+
+#[test]
+fn ratings_grow_with_rate() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    for name in ["MEGALOVANIA.sm", "Metro.sm", "Turbocharger.sm", "ZanderTwo.sm"] {
+        let sm = SmFile::from_file(dir.join(name)).unwrap();
+        for chart in &sm.charts {
+            let ratings = chart.difficulty_by_rate();
+            assert_eq!(ratings.len(), 14, "{}", name);
+            assert_eq!((ratings[0].rate, ratings[13].rate), (0.7, 2.0), "{}", name);
+            for pair in ratings.windows(2) {
+                assert!(pair[1].overall >= pair[0].overall, "{}: {:?}", name, pair);
+            }
+        }
+    }
+
+    // Twice the rate is twice the notes per second
+    let sm = repeated(150, &["1000", "0010", "0100", "0001"]);
+    let (normal, double) = (sm.charts[0].difficulty(1.0).unwrap(), sm.charts[0].difficulty(2.0).unwrap());
+    assert!((double.stream / normal.stream - 2.0).abs() < 0.01, "{:?} {:?}", normal, double);
+}
+
+#[test]
+fn ranks_bundled_charts_by_difficulty() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let overall = |name: &str| -> Vec<f64> {
+        let sm = SmFile::from_file(dir.join(name)).unwrap();
+        sm.charts.iter().map(|chart| chart.difficulty(1.0).unwrap().overall).collect()
+    };
+    let megalovania = overall("MEGALOVANIA.sm")[0];
+    let turbocharger = overall("Turbocharger.sm")[0];
+    let zander = overall("ZanderTwo.sm")[0];
+    let metro = overall("Metro.sm").into_iter().fold(0.0, f64::max);
+
+    assert!(megalovania > turbocharger, "{} {}", megalovania, turbocharger);
+    assert!(turbocharger > zander, "{} {}", turbocharger, zander);
+    assert!(zander > 2.0 * metro, "{} {}", zander, metro);
+}
+
+/// Overall rating and main skillset of each bundled chart at 1.0x and 1.5x,
+/// as this calculator rated them when they were pinned. A snapshot that
+/// makes changes to the ratings show up, not a check that they are right:
+/// they are not Etterna's MSD values, which the calculator does not match.
+const BUNDLED_RATINGS: [(&str, &str, f64, f64, Skillset); 18] = [
+    ("MEGALOVANIA.sm", "Challenge", 1.0, 34.87, Skillset::Jumpstream),
+    ("MEGALOVANIA.sm", "Challenge", 1.5, 50.57, Skillset::Jumpstream),
+    ("Metro.sm", "Beginner", 1.0, 0.00, Skillset::Stream),
+    ("Metro.sm", "Beginner", 1.5, 0.00, Skillset::Stream),
+    ("Metro.sm", "Easy", 1.0, 0.05, Skillset::JackSpeed),
+    ("Metro.sm", "Easy", 1.5, 0.08, Skillset::JackSpeed),
+    ("Metro.sm", "Medium", 1.0, 0.08, Skillset::JackSpeed),
+    ("Metro.sm", "Medium", 1.5, 0.12, Skillset::JackSpeed),
+    ("Metro.sm", "Hard", 1.0, 0.11, Skillset::JackSpeed),
+    ("Metro.sm", "Hard", 1.5, 0.16, Skillset::JackSpeed),
+    ("Metro.sm", "Challenge", 1.0, 0.13, Skillset::JackSpeed),
+    ("Metro.sm", "Challenge", 1.5, 0.20, Skillset::JackSpeed),
+    ("Metro.sm", "Edit", 1.0, 0.16, Skillset::JackSpeed),
+    ("Metro.sm", "Edit", 1.5, 0.24, Skillset::JackSpeed),
+    ("Turbocharger.sm", "Hard", 1.0, 22.45, Skillset::Stream),
+    ("Turbocharger.sm", "Hard", 1.5, 32.53, Skillset::Stream),
+    ("ZanderTwo.sm", "Hard", 1.0, 18.12, Skillset::Jumpstream),
+    ("ZanderTwo.sm", "Hard", 1.5, 28.33, Skillset::Jumpstream),
+];
+// Largest difference from a snapshot rating
+const RATING_TOLERANCE: f64 = 0.05;
+
+#[test]
+fn bundled_ratings_match_the_snapshot() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    for (name, difficulty, rate, overall, skillset) in BUNDLED_RATINGS {
+        let sm = SmFile::from_file(dir.join(name)).unwrap();
+        let chart = sm.charts.iter().find(|chart| chart.difficulty == difficulty).unwrap();
+        let rating = chart.difficulty(rate).unwrap();
+        assert!((rating.overall - overall).abs() <= RATING_TOLERANCE, "{} {} {}x: {:?}", name, difficulty, rate, rating);
+        assert_eq!(rating.main_skillset(), skillset, "{} {} {}x: {:?}", name, difficulty, rate, rating);
+    }
+}
+
+#[test]
+fn stamina_never_exceeds_the_density_skillsets() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    for name in ["MEGALOVANIA.sm", "Metro.sm", "Turbocharger.sm", "ZanderTwo.sm"] {
+        let sm = SmFile::from_file(dir.join(name)).unwrap();
+        for rating in sm.charts.iter().flat_map(|chart| chart.difficulty_by_rate()) {
+            let hardest = [rating.stream, rating.jumpstream, rating.handstream, rating.technical]
+                .into_iter()
+                .fold(0.0, f64::max);
+            assert!(rating.stamina <= hardest, "{}: {:?}", name, rating);
+            assert_ne!(rating.main_skillset(), Skillset::Stamina, "{}: {:?}", name, rating);
+        }
+    }
+
+    // A long uniform stream is nearly as hard for stamina as for stream
+    let rating = repeated(180, &["1000", "0010", "0100", "0001"]).charts[0].difficulty(1.0).unwrap();
+    assert!(rating.stamina < rating.stream && rating.stamina > 0.9 * rating.stream, "{:?}", rating);
+}
+
+#[test]
+fn short_charts_rate_lower() {
+    // The same stream for 32 measures (512 notes) and for 4 (64 notes)
+    let long = repeated(180, &["1000", "0010", "0100", "0001"]);
+    let measure = ["1000", "0010", "0100", "0001"].repeat(4).join("\n");
+    let short = SmFile::from_string(&format!(
+        "#BPMS:0=180;#NOTES:dance-single::Hard:10::\n{}\n;",
+        vec![measure; 4].join("\n,\n")
+    ))
+    .unwrap();
+    let (long, short) = (long.charts[0].difficulty(1.0).unwrap(), short.charts[0].difficulty(1.0).unwrap());
+    assert!((short.stream / long.stream - 64.0 / 200.0).abs() < 0.01, "{:?} {:?}", short, long);
+}
+
+#[test]
+fn long_stops_do_not_change_ratings() {
+    // 16 measures of stream, then 16 more after an 11.5-day stop
+    let measure = ["1000", "0010", "0100", "0001"].repeat(4).join("\n");
+    let chart = |stops: &str| {
+        SmFile::from_string(&format!(
+            "#BPMS:0=180;{}#NOTES:dance-single::Hard:10::\n{}\n;",
+            stops,
+            vec![measure.as_str(); 32].join("\n,\n")
+        ))
+        .unwrap()
+    };
+    let stopped = chart("#STOPS:64=1000000;").charts[0].difficulty(1.0).unwrap();
+    let plain = chart("").charts[0].difficulty(1.0).unwrap();
+    assert!((stopped.stream - plain.stream).abs() < 0.5, "{:?} {:?}", stopped, plain);
+}
+
+#[test]
+fn rejects_rates_outside_etterna_range() {
+    let sm = repeated(150, &["1000", "0100"]);
+    for rate in [0.5, 2.5] {
+        let err = sm.charts[0].difficulty(rate).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedRate { rate: found, min: 0.7, max: 2.0 } if found == rate));
+    }
+}
@@ -0,0 +1,86 @@
+use rotterna_lib::Error;
+use rotterna_lib::structs::SmFile;
+use std::path::PathBuf;
+
+fn parse_error(content: &str) -> Error {
+    SmFile::from_string(content).unwrap_err()
+}
+
+#[test]
+fn points_at_bad_note_lines() {
+    let err = parse_error("#BPMS:0=120;\n#NOTES:dance-single::Hard:1::\n1000\n,\n  10X0\n;");
+    assert!(
+        matches!(&err, Error::BadNoteLine { chart: 0, measure: 1, text, line: 5, column: 5 } if text == "10X0"),
+        "{:?}",
+        err
+    );
+    assert_eq!(
+        err.to_string(),
+        "line 5, column 5: chart 0 measure 1 has invalid note line '10X0'"
+    );
+
+    let err = parse_error("#BPMS:0=120;\n#NOTES:dance-single::Hard:1::\n1000\n10000\n;");
+    assert!(
+        matches!(err, Error::ColumnCountMismatch { expected: 4, found: 5, line: 4, .. }),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn points_at_malformed_values() {
+    let err = parse_error("#TITLE:Song;\n#BPMS:0=120,4=fast;");
+    assert!(
+        matches!(&err, Error::MalformedPair { tag, text, line: 2, column: 13 } if tag == "BPMS" && text == "4=fast"),
+        "{:?}",
+        err
+    );
+
+    let err = parse_error("#OFFSET:  soon;\n#BPMS:0=120;");
+    assert!(
+        matches!(&err, Error::MalformedValue { tag, line: 1, column: 11, .. } if tag == "OFFSET"),
+        "{:?}",
+        err
+    );
+
+    let err = parse_error("#BPMS:0=120;\n#NOTES:\n  dance-single:\n  :\n  Hard:\n  ten:\n  :\n1000\n;");
+    assert!(
+        matches!(&err, Error::MalformedValue { tag, text, line: 6, column: 3 } if tag == "NOTES" && text == "ten"),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn columns_count_escapes_as_written() {
+    // `0\=120` is read as `0=120`, but the `\` still takes a column
+    let err = parse_error("#BPMS:0\\=120,x=1;");
+    assert!(matches!(err, Error::MalformedPair { line: 1, column: 14, .. }), "{:?}", err);
+
+    // Escapes on earlier lines do not move columns on later ones
+    let err = parse_error("#BPMS:0\\=120,\n x=1;");
+    assert!(matches!(err, Error::MalformedPair { line: 2, column: 2, .. }), "{:?}", err);
+
+    let err = parse_error("#BPMS:0=120;\n#NOTES:dance-single:a\\:b:Hard:1::\n10\\X0\n;");
+    assert!(matches!(err, Error::BadNoteLine { line: 3, column: 4, .. }), "{:?}", err);
+}
+
+#[test]
+fn reports_missing_tags_and_stepstypes() {
+    let err = parse_error("#TITLE:Song;");
+    assert!(matches!(&err, Error::MissingTag { tag } if tag == "BPMS"), "{:?}", err);
+
+    let err = parse_error("#BPMS:0=120;\n#NOTES:\n     dance-quintuple:\n:Hard:1::\n1000\n;");
+    assert!(
+        matches!(&err, Error::UnknownStepsType { chart: 0, stepstype, line: 3 } if stepstype == "dance-quintuple"),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn io_errors_keep_their_source() {
+    let err = SmFile::from_file(PathBuf::from("assets/missing.sm")).unwrap_err();
+    assert!(matches!(err, Error::Io(_)));
+    assert!(std::error::Error::source(&err).is_some());
+}
@@ -0,0 +1,76 @@
+use rotterna_lib::structs::{Chart, Diagnostic, HoldNote, SmFile};
+
+/// The chart of a 120 BPM song whose note lines are `lines`, 4 to a measure
+/// (500ms apart).
+fn chart_of(lines: &[&str]) -> Chart {
+    let measures: Vec<String> = lines.chunks(4).map(|measure| measure.join("\n")).collect();
+    let sm = SmFile::from_string(&format!(
+        "#OFFSET:0;#BPMS:0=120;#NOTES:dance-single::Hard:1::\n{}\n;",
+        measures.join("\n,\n")
+    ))
+    .unwrap();
+    sm.charts.into_iter().next().unwrap()
+}
+
+fn hold(column: usize, start_ms: f64, end_ms: f64, is_roll: bool) -> HoldNote {
+    HoldNote {
+        column,
+        start_ms,
+        end_ms,
+        is_roll,
+    }
+}
+
+#[test]
+fn pairs_heads_with_the_next_tail_across_measures() {
+    let chart = chart_of(&["2000", "0400", "0000", "0000", "0300", "3000", "0000", "0000"]);
+    assert!(chart.diagnostics.is_empty(), "{:?}", chart.diagnostics);
+    assert_eq!(chart.holds, vec![hold(1, 500.0, 2000.0, true), hold(0, 0.0, 2500.0, false)]);
+}
+
+#[test]
+fn reports_heads_without_tails() {
+    let chart = chart_of(&["2000", "0200", "0300", "0000"]);
+    assert_eq!(chart.holds, vec![hold(1, 500.0, 1000.0, false)]);
+    assert_eq!(chart.diagnostics, vec![Diagnostic::UnterminatedHold { column: 0, start_ms: 0.0 }]);
+}
+
+#[test]
+fn reports_tails_without_heads() {
+    let chart = chart_of(&["1000", "3000", "0030", "0000"]);
+    assert!(chart.holds.is_empty());
+    assert_eq!(
+        chart.diagnostics,
+        vec![
+            Diagnostic::OrphanTail { column: 0, time_ms: 500.0 },
+            Diagnostic::OrphanTail { column: 2, time_ms: 1000.0 },
+        ]
+    );
+}
+
+#[test]
+fn a_second_head_leaves_the_first_unterminated() {
+    // The second head takes the tail, the first never ends
+    let chart = chart_of(&["4000", "2000", "3000", "0000"]);
+    assert_eq!(chart.holds, vec![hold(0, 500.0, 1000.0, false)]);
+    assert_eq!(chart.diagnostics, vec![Diagnostic::UnterminatedHold { column: 0, start_ms: 0.0 }]);
+}
+
+#[test]
+fn pairing_again_keeps_one_report_per_problem() {
+    let mut chart = chart_of(&["2300", "0003", "2000", "0000"]);
+    assert!(chart.holds.is_empty());
+    assert_eq!(chart.diagnostics.len(), 4);
+
+    // Pairing again does not report them twice
+    chart.pair_holds();
+    assert_eq!(
+        chart.diagnostics,
+        vec![
+            Diagnostic::OrphanTail { column: 1, time_ms: 0.0 },
+            Diagnostic::OrphanTail { column: 3, time_ms: 500.0 },
+            Diagnostic::UnterminatedHold { column: 0, start_ms: 0.0 },
+            Diagnostic::UnterminatedHold { column: 0, start_ms: 1000.0 },
+        ]
+    );
+}
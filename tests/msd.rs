@@ -0,0 +1,104 @@
+use rotterna_lib::decoding::msd::{Span, find_tag, tokenize};
+use rotterna_lib::structs::SmFile;
+
+/// (name, parameter values) of each tag.
+fn tags(content: &str) -> Vec<(String, Vec<String>)> {
+    tokenize(content)
+        .into_iter()
+        .map(|tag| (tag.name, tag.params.into_iter().map(|param| param.value).collect()))
+        .collect()
+}
+
+fn tag(name: &str, params: &[&str]) -> (String, Vec<String>) {
+    (name.to_string(), params.iter().map(|param| param.to_string()).collect())
+}
+
+#[test]
+fn splits_tags_and_parameters() {
+    let content = "#TITLE:Song;\n#NOTES:\n  dance-single:a:Hard:5::\n1000\n;";
+    assert_eq!(
+        tags(content),
+        vec![
+            tag("TITLE", &["Song"]),
+            tag("NOTES", &["\n  dance-single", "a", "Hard", "5", "", "\n1000\n"]),
+        ]
+    );
+
+    // Text outside tags is ignored, and names keep their case
+    assert_eq!(tags("junk\n#title:Song;junk"), vec![tag("title", &["Song"])]);
+    assert!(tokenize("#title:Song;")[0].is("TITLE"));
+}
+
+#[test]
+fn strips_comments() {
+    let content = "// #TITLE:Commented;\n#TITLE:Song // not part of it;\n;\n#ARTIST:Me;";
+    assert_eq!(tags(content), vec![tag("TITLE", &["Song \n"]), tag("ARTIST", &["Me"])]);
+
+    // A single '/' is text
+    assert_eq!(tags("#MUSIC:a/b.ogg;"), vec![tag("MUSIC", &["a/b.ogg"])]);
+}
+
+#[test]
+fn unescapes_characters() {
+    assert_eq!(tags("#TITLE:a\\;b\\:c\\\\d\\/\\/e;"), vec![tag("TITLE", &["a;b:c\\d//e"])]);
+
+    // The escaped character's offset is kept so positions can be mapped back
+    let title = &tokenize("#TITLE:a\\;b;")[0].params[0];
+    assert_eq!(title.escapes, vec![1]);
+    assert_eq!(title.position_at(1), (1, 10));
+    assert_eq!(title.position_at(2), (1, 11));
+}
+
+#[test]
+fn line_start_hash_closes_unterminated_tags() {
+    let content = "#TITLE:Song\n#ARTIST:Me;\n#SUBTITLE:No #1;\n  #GENRE:Rock";
+    assert_eq!(
+        tags(content),
+        vec![
+            tag("TITLE", &["Song\n"]),
+            tag("ARTIST", &["Me"]),
+            tag("SUBTITLE", &["No #1"]),
+            tag("GENRE", &["Rock"]),
+        ]
+    );
+
+    // The closed tag ends right before the next '#'
+    let tokens = tokenize(content);
+    assert_eq!(tokens[0].source(content), "#TITLE:Song\n");
+    assert_eq!(tokens[1].source(content), "#ARTIST:Me;");
+}
+
+#[test]
+fn colons_split_values_that_fields_join_back() {
+    assert_eq!(tags("#TITLE:Re:Zero;"), vec![tag("TITLE", &["Re", "Zero"])]);
+
+    let sm = SmFile::from_string("#TITLE:Re:Zero;\n#ARTIST:A\\:B;\n#BPMS:0=120;").unwrap();
+    assert_eq!(sm.metadata.title, "Re:Zero");
+    assert_eq!(sm.metadata.artist, "A:B");
+}
+
+#[test]
+fn records_spans() {
+    let content = "#TITLE:Song;\n  #BPMS:0=120;";
+    let tokens = tokenize(content);
+    let bpms = find_tag(&tokens, "bpms").unwrap();
+    assert_eq!(
+        bpms.span,
+        Span {
+            offset: 15,
+            len: 12,
+            line: 2,
+            column: 3,
+        }
+    );
+    assert_eq!(
+        bpms.params[0].span,
+        Span {
+            offset: 21,
+            len: 5,
+            line: 2,
+            column: 9,
+        }
+    );
+    assert_eq!(bpms.value(), "0=120");
+}
@@ -0,0 +1,457 @@
+use rotterna_lib::Error;
+use rotterna_lib::converter::osu::to_osu;
+use rotterna_lib::converter::{create_basic_osu, from_osu};
+use rotterna_lib::structs::{
+    Chart, Diagnostic, LongNoteMode, MineMode, NoteType, OsuBeatmap, OsuEvent, OsuHitObjectKind,
+    OsuSettings, PreviewTime, ScrollSpeedMode, SmFile,
+};
+use rotterna_lib::timing::Row;
+use std::path::PathBuf;
+
+const MAP: &str = "\u{feff}osu file format v14
+
+[General]
+AudioFilename: audio.mp3
+AudioLeadIn: 0
+PreviewTime: 34826
+Mode: 3
+SpecialStyle: 0
+EditorBookmarkShown: 1
+
+[Editor]
+Bookmarks: 1000,2000
+BeatDivisor: 4
+
+[Metadata]
+Title:Song
+TitleUnicode:曲
+Artist:Artist
+Creator:Mapper
+Version:Hard
+Tags:one two
+BeatmapID:123
+BeatmapSetID:-1
+Genre:Rock
+
+[Difficulty]
+HPDrainRate:8
+CircleSize:7
+OverallDifficulty:8.5
+SliderMultiplier:1.4
+SliderTickRate:1
+KeyLayout:custom
+
+[Events]
+//Background and Video events
+0,0,\"bg.jpg\",0,0
+2,10000,12000
+Sprite,Foreground,Centre,\"sb.png\",320,240
+ F,0,0,1000,0,1
+
+[TimingPoints]
+0,500,4,2,0,100,1,0
+1000,-50,4,2,0,100,0,0
+2000,250
+
+[Colours]
+Combo1 : 255,128,0
+
+[HitObjects]
+36,192,1000,1,0,0:0:0:0:
+109,192,1500,128,0,2000:0:0:0:0:
+256,192,2500,12,0,3000,0:0:0:0:
+100,100,3500,2,0,B|200:100,1,100
+";
+
+#[test]
+fn reads_every_section() {
+    let beatmap = OsuBeatmap::from_string(MAP).unwrap();
+    assert_eq!(beatmap.format_version, 14);
+    assert_eq!(beatmap.general.audio_filename, "audio.mp3");
+    assert_eq!(beatmap.general.preview_time, 34826);
+    assert_eq!(beatmap.general.mode, 3);
+    assert_eq!(beatmap.general.other, vec![("EditorBookmarkShown".to_string(), "1".to_string())]);
+    assert_eq!(beatmap.editor.bookmarks, vec![1000, 2000]);
+    assert_eq!(beatmap.metadata.title_unicode, "曲");
+    assert_eq!(beatmap.metadata.tags, vec!["one", "two"]);
+    assert_eq!(beatmap.metadata.beatmap_id, 123);
+    assert_eq!(beatmap.metadata.other, vec![("Genre".to_string(), "Rock".to_string())]);
+    assert_eq!(beatmap.difficulty.overall_difficulty, 8.5);
+    assert_eq!(beatmap.difficulty.approach_rate, 8.5); // Missing, defaults to OD
+    // Unknown keys are kept whatever their value
+    assert_eq!(beatmap.difficulty.other, vec![("KeyLayout".to_string(), "custom".to_string())]);
+    assert_eq!(beatmap.key_count(), 7);
+    assert_eq!(beatmap.colours, vec![("Combo1".to_string(), [255, 128, 0])]);
+
+    assert_eq!(beatmap.events.len(), 4);
+    assert_eq!(
+        beatmap.events[0],
+        OsuEvent::Background { filename: "bg.jpg".to_string(), x: 0, y: 0 }
+    );
+    assert_eq!(beatmap.events[1], OsuEvent::Break { start_time: 10000, end_time: 12000 });
+    assert_eq!(beatmap.events[3], OsuEvent::Other(" F,0,0,1000,0,1".to_string()));
+
+    assert_eq!(beatmap.timing_points.len(), 3);
+    assert!(!beatmap.timing_points[1].uninherited);
+    assert_eq!(beatmap.timing_points[2].meter, 4); // Old short format
+
+    let objects = &beatmap.hit_objects;
+    assert_eq!(objects.len(), 4);
+    assert_eq!(objects[0].kind, OsuHitObjectKind::Circle);
+    assert_eq!(objects[0].column(7), 0);
+    assert_eq!(objects[1].kind, OsuHitObjectKind::Hold { end_time: 2000 });
+    assert_eq!(objects[1].column(7), 1);
+    assert_eq!(objects[1].hit_sample, "0:0:0:0:");
+    assert_eq!(objects[2].kind, OsuHitObjectKind::Spinner { end_time: 3000 });
+    assert_eq!(objects[3].kind, OsuHitObjectKind::Slider { params: "B|200:100,1,100".to_string() });
+}
+
+#[test]
+fn written_beatmaps_read_back_the_same() {
+    let beatmap = OsuBeatmap::from_string(MAP).unwrap();
+    let written = beatmap.to_string();
+    assert_eq!(OsuBeatmap::from_string(&written).unwrap(), beatmap);
+    assert!(written.contains("\n109,192,1500,128,0,2000:0:0:0:0:\n"));
+    assert!(written.contains("\n256,192,2500,12,0,3000,0:0:0:0:\n"));
+    assert!(written.contains("\n F,0,0,1000,0,1\n"));
+}
+
+#[test]
+fn converter_fills_beatmap_from_the_song() {
+    let sm = SmFile::from_string(
+        "#TITLE:曲;#TITLETRANSLIT:Song;#ARTIST:Artist;#GENRE:Happy Hardcore;\
+         #SAMPLESTART:12.5;#BPMS:0=120;\
+         #NOTES:kb7-single::Hard:10::\n1000000\n0000000\n0000000\n0000000\n;",
+    )
+    .unwrap();
+    let settings = OsuSettings::new().hp(7.0);
+    let beatmap = to_osu(&sm, &sm.charts[0], &settings).unwrap();
+
+    assert_eq!(beatmap.difficulty.circle_size, 7.0);
+    assert_eq!(beatmap.key_count(), 7);
+    assert_eq!(beatmap.metadata.title, "Song");
+    assert_eq!(beatmap.metadata.title_unicode, "曲");
+    assert_eq!(beatmap.metadata.artist, "Artist");
+    assert_eq!(beatmap.metadata.artist_unicode, "Artist");
+    assert_eq!(beatmap.metadata.tags, vec!["Happy", "Hardcore", "kb7-single", "rOtterna"]);
+    assert_eq!(beatmap.general.preview_time, 12500);
+    assert_eq!(beatmap.hit_objects.len(), 1);
+    assert_eq!(beatmap.hit_objects[0].column(7), 0);
+}
+
+#[test]
+fn converter_applies_settings() {
+    let sm = SmFile::from_string(
+        "#CREDIT:Stepper;#BPMS:0=120;#SCROLLS:1=2;\
+         #NOTES:dance-single:Hi:Hard:10::\n2040\n3030\nM001\n0000\n;",
+    )
+    .unwrap();
+    let settings = OsuSettings::new()
+        .creator("Mapper")
+        .version_template("{difficulty} {meter} ({description})")
+        .tags(&["converted"])
+        .source("Game")
+        .preview_time(PreviewTime::At(3000))
+        .audio_lead_in(1000)
+        .scroll_speed(ScrollSpeedMode::StopsAndScrolls)
+        .long_notes(LongNoteMode::HoldsOnly)
+        .mines(MineMode::Taps)
+        .key_count(3);
+    let beatmap = to_osu(&sm, &sm.charts[0], &settings).unwrap();
+
+    assert_eq!(beatmap.metadata.creator, "Mapper");
+    assert_eq!(beatmap.metadata.version, "Hard 10 (Hi)");
+    assert_eq!(beatmap.metadata.tags, vec!["dance-single", "converted"]);
+    assert_eq!(beatmap.metadata.source, "Game");
+    assert_eq!(beatmap.general.preview_time, 3000);
+    assert_eq!(beatmap.general.audio_lead_in, 1000);
+    assert_eq!(beatmap.key_count(), 3);
+
+    // The roll is a tap, the mine too, the fourth column is dropped
+    let objects: Vec<(i32, usize, &OsuHitObjectKind)> = beatmap
+        .hit_objects
+        .iter()
+        .map(|object| (object.time, object.column(3), &object.kind))
+        .collect();
+    assert_eq!(
+        objects,
+        vec![
+            (0, 0, &OsuHitObjectKind::Hold { end_time: 500 }),
+            (0, 2, &OsuHitObjectKind::Circle),
+            (1000, 0, &OsuHitObjectKind::Circle),
+        ]
+    );
+
+    let points: Vec<(f64, f64, bool)> = beatmap
+        .timing_points
+        .iter()
+        .map(|point| (point.time, point.beat_length, point.uninherited))
+        .collect();
+    assert_eq!(points, vec![(0.0, 500.0, true), (500.0, -50.0, false)]);
+}
+
+#[test]
+fn converter_turns_short_long_notes_into_taps() {
+    let sm = SmFile::from_string("#BPMS:0=120;#NOTES:dance-single::Hard:10::\n2400\n3000\n0300\n0000\n;").unwrap();
+    let settings = OsuSettings::new().min_long_note_ms(600.0);
+    let kinds: Vec<OsuHitObjectKind> = to_osu(&sm, &sm.charts[0], &settings).unwrap()
+        .hit_objects
+        .into_iter()
+        .map(|object| object.kind)
+        .collect();
+    assert_eq!(kinds, vec![OsuHitObjectKind::Circle, OsuHitObjectKind::Hold { end_time: 1000 }]);
+
+    let settings = OsuSettings::new().long_notes(LongNoteMode::None);
+    let beatmap = to_osu(&sm, &sm.charts[0], &settings).unwrap();
+    assert!(beatmap.hit_objects.iter().all(|object| object.kind == OsuHitObjectKind::Circle));
+}
+
+#[test]
+fn converter_applies_rate() {
+    let sm = SmFile::from_string(
+        "#MUSIC:song.ogg;#SAMPLESTART:12;#BPMS:0=120;#STOPS:1=0.6;\
+         #NOTES:dance-single::Hard:10::\n1000\n0100\n2000\n3000\n;",
+    )
+    .unwrap();
+    let settings = OsuSettings::new().rate(1.2).audio_filename_template("{stem}_{rate}x.{extension}");
+    let beatmap = to_osu(&sm, &sm.charts[0], &settings).unwrap();
+
+    assert_eq!(beatmap.metadata.version, "Hard (1.2x)");
+    assert_eq!(beatmap.general.audio_filename, "song_1.2x.ogg");
+    assert_eq!(beatmap.general.preview_time, 10000);
+    // Beats of 500ms become 416.67ms, the 600ms stop 500ms
+    let times: Vec<i32> = beatmap.hit_objects.iter().map(|object| object.time).collect();
+    assert_eq!(times, vec![0, 417, 1333]);
+    assert_eq!(beatmap.hit_objects[2].kind, OsuHitObjectKind::Hold { end_time: 1750 });
+    assert!((beatmap.timing_points[0].beat_length - 500.0 / 1.2).abs() < 1e-9);
+
+    let settings = OsuSettings::new().rate(3.5);
+    assert!(matches!(to_osu(&sm, &sm.charts[0], &settings), Err(Error::UnsupportedRate { .. })));
+    assert!(matches!(create_basic_osu(&sm, &sm.charts[0], &settings), Err(Error::UnsupportedRate { .. })));
+}
+
+#[test]
+fn converter_writes_break_periods() {
+    // A note, 8 empty beats at 120 BPM (4 seconds), then a note
+    let sm = SmFile::from_string(
+        "#BPMS:0=120;#NOTES:dance-single::Hard:10::\n1000\n0000\n0000\n0000\n,\n\
+         0000\n0000\n0000\n0000\n,\n0000\n0100\n0000\n0000\n;",
+    )
+    .unwrap();
+    let breaks = |settings: &OsuSettings| -> Vec<OsuEvent> {
+        let beatmap = to_osu(&sm, &sm.charts[0], settings).unwrap();
+        beatmap.events.into_iter().filter(|event| matches!(event, OsuEvent::Break { .. })).collect()
+    };
+
+    assert!(breaks(&OsuSettings::new()).is_empty());
+    let settings = OsuSettings::new().min_break_ms(4000.0);
+    assert_eq!(breaks(&settings), vec![OsuEvent::Break { start_time: 200, end_time: 4300 }]);
+    let osu = create_basic_osu(&sm, &sm.charts[0], &settings).unwrap();
+    assert!(osu.contains("//Break Periods\n2,200,4300\n"));
+}
+
+#[test]
+fn reports_malformed_lines() {
+    let err = OsuBeatmap::from_string("osu file format v14\n[TimingPoints]\n0,abc\n").unwrap_err();
+    assert!(matches!(err, Error::MalformedOsuLine { ref section, line: 3, .. } if section == "TimingPoints"));
+
+    let err = OsuBeatmap::from_string("#TITLE:not osu;").unwrap_err();
+    assert!(matches!(err, Error::MalformedOsuLine { ref section, line: 1, .. } if section.is_empty()));
+}
+
+#[test]
+fn reads_converter_output() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let settings = OsuSettings::new().hp(7.0);
+    for name in ["MEGALOVANIA.sm", "Metro.sm", "Turbocharger.sm", "ZanderTwo.sm"] {
+        let sm = SmFile::from_file(dir.join(name)).unwrap();
+        for chart in &sm.charts {
+            let osu = create_basic_osu(&sm, chart, &settings).unwrap();
+            let beatmap = OsuBeatmap::from_string(&osu).unwrap();
+
+            assert_eq!(beatmap.general.mode, 3, "{}", name);
+            assert_eq!(beatmap.metadata.title, sm.metadata.title, "{}", name);
+            assert_eq!(beatmap.metadata.version, chart.difficulty, "{}", name);
+            assert_eq!(beatmap.difficulty.overall_difficulty, 8.0, "{}", name);
+
+            let notes = chart
+                .measures
+                .iter()
+                .flat_map(|measure| measure.beats.iter())
+                .filter(|beat| beat.judged)
+                .map(|beat| beat.notes.iter().filter(|note| note.is_note()).count())
+                .sum::<usize>();
+            assert_eq!(beatmap.hit_objects.len(), notes, "{}", name);
+            let holds = beatmap
+                .hit_objects
+                .iter()
+                .filter(|object| matches!(object.kind, OsuHitObjectKind::Hold { .. }))
+                .count();
+            assert_eq!(holds, chart.holds.len(), "{}", name);
+            assert!(beatmap.timing_points.iter().any(|point| point.uninherited), "{}", name);
+        }
+    }
+}
+
+/// Rows and note lines of the notes of `chart`.
+fn note_rows(chart: &Chart) -> Vec<(Row, String)> {
+    chart
+        .measures
+        .iter()
+        .flat_map(|measure| measure.beats.iter())
+        .filter(|beat| beat.notes.iter().any(|note| *note != NoteType::Empty))
+        .map(|beat| (beat.row, beat.notes.iter().map(|note| note.to_char()).collect()))
+        .collect()
+}
+
+#[test]
+fn converts_converter_output_back_to_the_same_rows() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let settings = OsuSettings::new().hp(7.0);
+    for name in ["MEGALOVANIA.sm", "Metro.sm", "Turbocharger.sm", "ZanderTwo.sm"] {
+        let sm = SmFile::from_file(dir.join(name)).unwrap();
+        for chart in &sm.charts {
+            let osu = create_basic_osu(&sm, chart, &settings).unwrap();
+            let converted = from_osu(&OsuBeatmap::from_string(&osu).unwrap()).unwrap();
+            let back = &converted.charts[0];
+
+            assert_eq!(back.stepstype, chart.stepstype, "{}", name);
+            assert_eq!(back.description, chart.difficulty, "{}", name);
+            assert!(back.diagnostics.is_empty(), "{}: {:?}", name, back.diagnostics);
+            assert_eq!(note_rows(back), note_rows(chart), "{}", name);
+            assert_eq!(back.holds.len(), chart.holds.len(), "{}", name);
+            for (hold, original) in back.holds.iter().zip(&chart.holds) {
+                assert!((hold.start_ms - original.start_ms).abs() < 1.0, "{}", name);
+                assert!((hold.end_ms - original.end_ms).abs() < 1.0, "{}", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn converts_timing_and_metadata() {
+    let beatmap = OsuBeatmap::from_string(MAP).unwrap();
+    let sm = from_osu(&beatmap).unwrap();
+    assert_eq!(sm.metadata.title, "曲");
+    assert_eq!(sm.metadata.title_translit, "Song");
+    assert_eq!(sm.metadata.artist, "Artist");
+    assert_eq!(sm.metadata.credit, "Mapper");
+    assert_eq!(sm.metadata.music, "audio.mp3");
+    assert_eq!(sm.metadata.background, "bg.jpg");
+    assert_eq!(sm.metadata.sample_start, 34826.0);
+    assert_eq!(sm.offset, 0.0);
+
+    // The 250ms/beat point at 2000ms lands on beat 4
+    assert_eq!(sm.bpms, vec![(Row::ZERO, 120.0), (Row::from_rows(4 * 48), 240.0)]);
+
+    let chart = &sm.charts[0];
+    assert_eq!(chart.stepstype, "kb7-single");
+    assert_eq!(chart.description, "Hard");
+    assert_eq!(chart.holds.len(), 1);
+    assert_eq!(chart.holds[0].start_ms, 1500.0);
+    assert_eq!(chart.holds[0].end_ms, 2000.0);
+}
+
+#[test]
+fn reports_notes_off_every_snap() {
+    let map = "osu file format v14\n[General]\nMode: 3\n[Difficulty]\nCircleSize:4\n\
+        [TimingPoints]\n0,2000,4,2,0,100,1,0\n[HitObjects]\n64,192,1000,1,0,0:0:0:0:\n192,192,1020,1,0,0:0:0:0:\n";
+    let sm = from_osu(&OsuBeatmap::from_string(map).unwrap()).unwrap();
+    let chart = &sm.charts[0];
+
+    // At 30 BPM a 192nd is 41.7ms long; 1020ms is 20ms from the nearest one,
+    // which the note at 1000ms is on, so it goes to the next one
+    assert_eq!(chart.diagnostics.len(), 1);
+    assert!(matches!(
+        chart.diagnostics[0],
+        Diagnostic::OffSnapNote { column: 1, time_ms, snapped_ms } if time_ms == 1020.0 && (snapped_ms - 1041.667).abs() < 0.001
+    ));
+    assert_eq!(
+        note_rows(chart),
+        vec![(Row::from_rows(24), "1000".to_string()), (Row::from_rows(25), "0100".to_string())]
+    );
+}
+
+#[test]
+fn reports_notes_on_taken_cells() {
+    // A tap 1ms after a long note's tail snaps onto it, and one under another
+    // tap; the one on column 1 is kept
+    let map = "osu file format v14\n[General]\nMode: 3\n[Difficulty]\nCircleSize:4\n\
+        [TimingPoints]\n0,500,4,2,0,100,1,0\n[HitObjects]\n64,192,0,128,0,1000:0:0:0:0:\n\
+        64,192,1001,1,0,0:0:0:0:\n192,192,1001,1,0,0:0:0:0:\n192,192,1500,1,0,0:0:0:0:\n192,192,1500,1,0,0:0:0:0:\n";
+    let sm = from_osu(&OsuBeatmap::from_string(map).unwrap()).unwrap();
+    let chart = &sm.charts[0];
+    assert_eq!(
+        chart.diagnostics,
+        vec![
+            Diagnostic::OverlappingNote { column: 0, time_ms: 1001.0 },
+            Diagnostic::OverlappingNote { column: 1, time_ms: 1500.0 },
+        ]
+    );
+    assert_eq!(
+        note_rows(chart),
+        vec![
+            (Row::ZERO, "2000".to_string()),
+            (Row::from_rows(96), "3100".to_string()),
+            (Row::from_rows(144), "0100".to_string()),
+        ]
+    );
+    assert_eq!(chart.holds.len(), 1);
+}
+
+/// A 4-key beatmap with `timing_points` and a tap on column 0 at each of `times`.
+fn mania_map(timing_points: &[&str], times: &[i32]) -> OsuBeatmap {
+    let objects: Vec<String> = times.iter().map(|time| format!("64,192,{},1,0,0:0:0:0:", time)).collect();
+    OsuBeatmap::from_string(&format!(
+        "osu file format v14\n[General]\nMode: 3\n[Difficulty]\nCircleSize:4\n[TimingPoints]\n{}\n[HitObjects]\n{}\n",
+        timing_points.join("\n"),
+        objects.join("\n")
+    ))
+    .unwrap()
+}
+
+#[test]
+fn keeps_bpms_and_places_changes_between_beats() {
+    // 240 BPM from 1250ms, beat 2.5 at 120 BPM; 1346ms is 2.2ms
+    // from the nearest 192nd. Beat 4 is 1.116 beats after it, at 1904ms
+    let beatmap = mania_map(&["0,500,4,2,0,100,1,0", "1250,250,4,2,0,100,1,0", "1346,500,4,2,0,100,1,0"], &[0, 1250, 1904]);
+    let sm = from_osu(&beatmap).unwrap();
+    assert_eq!(
+        sm.bpms,
+        vec![(Row::ZERO, 120.0), (Row::from_rows(120), 240.0), (Row::new(2884 * 48, 1000), 120.0)]
+    );
+    let chart = &sm.charts[0];
+    assert!(chart.diagnostics.is_empty(), "{:?}", chart.diagnostics);
+    let times: Vec<f64> = chart.measures.iter().flat_map(|measure| measure.beats.iter()).map(|beat| beat.time).collect();
+    assert_eq!(times.len(), 3);
+    assert!(times.iter().zip([0.0, 1250.0, 1904.0]).all(|(time, original)| (time - original).abs() < 1.0), "{:?}", times);
+}
+
+#[test]
+fn converts_pauses_back_to_stops_and_delays() {
+    // A stop on beat 1 and a delay on beat 3, both 750ms, at 120 BPM
+    let sm = SmFile::from_string(
+        "#OFFSET:0;#BPMS:0=120;#STOPS:1=0.75;#DELAYS:3=0.75;\
+         #NOTES:dance-single::Hard:1::\n1000\n0100\n0010\n0001\n,\n1000\n0000\n0000\n0000\n;",
+    )
+    .unwrap();
+    let osu = create_basic_osu(&sm, &sm.charts[0], &OsuSettings::new()).unwrap();
+    let back = from_osu(&OsuBeatmap::from_string(&osu).unwrap()).unwrap();
+
+    // The resume points are not BPM changes
+    assert_eq!(back.bpms, vec![(Row::ZERO, 120.0)]);
+    assert_eq!(back.stops, vec![(Row::from_rows(48), 0.75)]);
+    assert_eq!(back.segments.delays, vec![(Row::from_rows(144), 0.75)]);
+    assert_eq!(note_rows(&back.charts[0]), note_rows(&sm.charts[0]));
+}
+
+#[test]
+fn rejects_unsupported_maps() {
+    let mut beatmap = OsuBeatmap::from_string(MAP).unwrap();
+    beatmap.difficulty.circle_size = 11.0;
+    assert!(matches!(from_osu(&beatmap), Err(Error::UnsupportedOsuMap { .. })));
+
+    beatmap.difficulty.circle_size = 4.0;
+    beatmap.general.mode = 0;
+    assert!(matches!(from_osu(&beatmap), Err(Error::UnsupportedOsuMap { .. })));
+}
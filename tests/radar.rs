@@ -0,0 +1,59 @@
+use rotterna_lib::radar::RadarValues;
+use rotterna_lib::structs::SmFile;
+use std::path::PathBuf;
+
+#[test]
+fn computes_groove_radar_and_counts() {
+    // 120 BPM: a beat is 500ms. The third measure has 12ths, off the 8th grid
+    let sm = SmFile::from_string(
+        "#OFFSET:0;#BPMS:0=120;#NOTES:dance-single::Hard:10::\n\
+         1100\n2000\n0110\n3000\n,\n\
+         M001\n1011\n4000\n3L00\n,\n\
+         1000\n0100\n0010\n,\n\
+         F000\n0000\n0000\n0000\n;",
+    )
+    .unwrap();
+    let radar = sm.charts[0].compute_radar_values();
+
+    assert_eq!(
+        (radar.notes, radar.taps_and_holds, radar.jumps, radar.hands),
+        (14, 9, 3, 2)
+    );
+    assert_eq!(
+        (radar.holds, radar.rolls, radar.mines, radar.lifts, radar.fakes),
+        (1, 1, 1, 1, 1)
+    );
+    // The last note is at 5333ms
+    let seconds = 16.0 / 3.0;
+    assert!((radar.stream - 14.0 / seconds / 7.0).abs() < 1e-9);
+    assert!((radar.voltage - 1.375 * 12.0 / seconds / 10.0).abs() < 1e-9);
+    assert!((radar.air - 3.0 / seconds).abs() < 1e-9);
+    assert!((radar.freeze - 1.0 / seconds).abs() < 1e-9);
+    assert!((radar.chaos - 2.0 / seconds / 2.0).abs() < 1e-9);
+    assert_eq!(radar.to_vec().len(), 14);
+}
+
+#[test]
+fn empty_charts_have_no_radar() {
+    let sm = SmFile::from_string("#BPMS:0=120;#NOTES:dance-single::Hard:1::\n0000\n;").unwrap();
+    assert_eq!(sm.charts[0].compute_radar_values(), RadarValues::default());
+}
+
+#[test]
+fn matches_the_radar_values_stepmania_wrote() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/MEGALOVANIA.sm");
+    let sm = SmFile::from_file(path).unwrap();
+    let chart = &sm.charts[0];
+    let radar = chart.compute_radar_values();
+
+    // Written with StepMania 3.95's categories: the groove radar, then
+    // taps and holds, jumps, holds, mines, hands and rolls
+    let file = &chart.radar_values;
+    assert_eq!([radar.stream, radar.voltage, radar.air, radar.chaos], [file[0], file[1], file[2], file[4]]);
+    assert_eq!(
+        [radar.taps_and_holds, radar.jumps, radar.holds, radar.mines, radar.rolls],
+        [file[5], file[6], file[7], file[8], file[10]].map(|count| count as u32)
+    );
+    // The song length is the music's in StepMania, slightly past the last note here
+    assert!((radar.freeze - file[3]).abs() < 0.02, "{} {}", radar.freeze, file[3]);
+}
@@ -0,0 +1,225 @@
+use rotterna_lib::decoding::msd;
+use rotterna_lib::structs::{Chart, NoteType, SmFile, SmWriteSettings};
+use rotterna_lib::timing::Row;
+use std::path::PathBuf;
+
+/// Every .sm file in `assets/`, with its name.
+fn assets() -> Vec<(String, SmFile)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sm"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty());
+
+    files
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let sm = SmFile::from_file(path).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            (name, sm)
+        })
+        .collect()
+}
+
+fn reparse(sm: &SmFile) -> SmFile {
+    SmFile::from_string(&sm.to_sm_string()).unwrap()
+}
+
+/// Non-empty note lines of each chart, as (row, notes).
+fn note_rows(sm: &SmFile) -> Vec<Vec<(Row, String)>> {
+    sm.charts
+        .iter()
+        .map(|chart| {
+            chart
+                .measures
+                .iter()
+                .flat_map(|measure| measure.beats.iter())
+                .filter(|beat| beat.notes.iter().any(|note| *note != NoteType::Empty))
+                .map(|beat| (beat.row, beat.notes.iter().map(|note| note.to_char()).collect()))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn round_trip_keeps_song_tags() {
+    for (name, sm) in assets() {
+        let written = reparse(&sm);
+        assert_eq!(written.metadata, sm.metadata, "{}", name);
+        assert_eq!(written.offset, sm.offset, "{}", name);
+        assert_eq!(written.bpms, sm.bpms, "{}", name);
+        assert_eq!(written.stops, sm.stops, "{}", name);
+        assert_eq!(written.segments, sm.segments, "{}", name);
+        assert_eq!(written.other_tags, sm.other_tags, "{}", name);
+    }
+}
+
+#[test]
+fn round_trip_keeps_charts() {
+    for (name, sm) in assets() {
+        let written = reparse(&sm);
+        assert_eq!(written.charts.len(), sm.charts.len(), "{}", name);
+        for (chart, original) in written.charts.iter().zip(&sm.charts) {
+            assert_eq!(chart.stepstype, original.stepstype, "{}", name);
+            assert_eq!(chart.description, original.description, "{}", name);
+            assert_eq!(chart.difficulty, original.difficulty, "{}", name);
+            assert_eq!(chart.meter, original.meter, "{}", name);
+            assert_eq!(chart.radar_values, original.radar_values, "{}", name);
+            assert_eq!(chart.holds, original.holds, "{}", name);
+        }
+        assert_eq!(note_rows(&written), note_rows(&sm), "{}", name);
+    }
+}
+
+#[test]
+fn round_trip_keeps_tag_order() {
+    for (name, sm) in assets() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets").join(&name);
+        let names = |content: &str| -> Vec<String> {
+            msd::tokenize(content)
+                .into_iter()
+                .map(|tag| tag.name.to_uppercase())
+                .filter(|tag| tag != "NOTES")
+                .collect()
+        };
+        let original = std::fs::read_to_string(path).unwrap();
+        assert_eq!(names(&sm.to_sm_string()), names(&original), "{}", name);
+    }
+}
+
+#[test]
+fn writing_is_stable() {
+    for (name, sm) in assets() {
+        let text = sm.to_sm_string();
+        assert_eq!(reparse(&sm).to_sm_string(), text, "{}", name);
+    }
+}
+
+#[test]
+fn unknown_tags_are_written_verbatim() {
+    let sm = SmFile::from_string(
+        "#TITLE:Song;\n#BPMS:0=120;\n#BGCHANGES:0.000=bg.png=1.000=0=0=1;\n#MADEUP:a:b;\n",
+    )
+    .unwrap();
+    let text = sm.to_sm_string();
+    assert!(text.contains("#BGCHANGES:0.000=bg.png=1.000=0=0=1;\n"));
+    assert!(text.contains("#MADEUP:a:b;\n"));
+}
+
+#[test]
+fn rated_songs_keep_rows_and_scale_times() {
+    for (name, sm) in assets() {
+        let rated = sm.with_rate(1.2).unwrap();
+        assert_eq!(note_rows(&rated), note_rows(&sm), "{}", name);
+        assert_eq!(rated.bpms[0].1, sm.bpms[0].1 * 1.2, "{}", name);
+        assert_eq!(rated.offset, sm.offset / 1.2, "{}", name);
+        assert_eq!(rated.metadata.sample_start, sm.metadata.sample_start / 1.2, "{}", name);
+
+        // The rated file times its notes like the scaled chart
+        let written = reparse(&rated);
+        for (chart, original) in written.charts.iter().zip(&sm.charts) {
+            let times = |chart: &Chart| -> Vec<f64> {
+                chart.measures.iter().flat_map(|measure| measure.beats.iter()).map(|beat| beat.time).collect()
+            };
+            for (time, original) in times(chart).into_iter().zip(times(original)) {
+                assert!((time - original / 1.2).abs() < 0.01, "{}: {} {}", name, time, original);
+            }
+        }
+    }
+
+    let (_, sm) = &assets()[0];
+    assert!(sm.with_rate(0.4).is_err());
+    assert!(sm.with_rate(3.5).is_err());
+}
+
+#[test]
+fn writer_refreshes_radar_values() {
+    let settings = SmWriteSettings::new().refresh_radar_values(true);
+    for (name, sm) in assets() {
+        let written = SmFile::from_string(&sm.to_sm_string_with(&settings)).unwrap();
+        for (chart, original) in written.charts.iter().zip(&sm.charts) {
+            let computed = original.compute_radar_values().to_vec().repeat(2);
+            assert_eq!(chart.radar_values.len(), computed.len(), "{}", name);
+            for (value, expected) in chart.radar_values.iter().zip(&computed) {
+                assert!((value - expected).abs() < 1e-6, "{}: {} {}", name, value, expected);
+            }
+        }
+        // Without the option, the parsed values are kept
+        assert_eq!(reparse(&sm).charts[0].radar_values, sm.charts[0].radar_values, "{}", name);
+    }
+}
+
+/// A 60 BPM song with `timing_tags` and a 12th-note measure with a tap on row 16.
+fn twelfth_note(timing_tags: &str) -> SmFile {
+    let mut lines = ["0000"; 12];
+    lines[1] = "1000";
+    SmFile::from_string(&format!(
+        "#OFFSET:0;#BPMS:0=60;{}#NOTES:dance-single::Hard:1::\n{}\n;",
+        timing_tags,
+        lines.join("\n")
+    ))
+    .unwrap()
+}
+
+/// Row and time of the chart's only note.
+fn note_time(sm: &SmFile) -> (Row, f64) {
+    let beat = sm.charts[0].measures[0]
+        .beats
+        .iter()
+        .find(|beat| beat.notes.iter().any(|note| note.is_note()))
+        .unwrap();
+    (beat.row, beat.time)
+}
+
+#[test]
+fn rounded_beats_time_their_row() {
+    // StepMania writes a 12th as 0.333: the stop is on row 16, after its note
+    let sm = twelfth_note("#STOPS:0.333=1.000;");
+    assert_eq!(sm.stops, vec![(Row::from_rows(16), 1.0)]);
+    let (row, time) = note_time(&sm);
+    assert_eq!(row, Row::from_rows(16));
+    assert!((time - 1000.0 / 3.0).abs() < 1e-9, "{}", time);
+
+    // A digit more than StepMania writes is still within half a digit
+    assert_eq!(twelfth_note("#STOPS:0.3333=1.000;").stops[0].0, Row::from_rows(16));
+    // Beats that are not near a whole row stay exact
+    assert_eq!(twelfth_note("#STOPS:0.330=1.000;").stops[0].0, Row::new(1584, 100));
+}
+
+#[test]
+fn written_beats_read_back_as_their_row() {
+    let mut sm = twelfth_note("");
+    // A 12th, then a 16th and a 192nd (1/48 beat) after the note
+    sm.stops = vec![(Row::from_rows(16), 1.0)];
+    sm.segments.delays = vec![(Row::from_rows(36), 0.5), (Row::from_rows(49), 0.25)];
+    let text = sm.to_sm_string();
+    assert!(text.contains("#STOPS:0.333=1.000;"), "{}", text);
+    assert!(text.contains("#DELAYS:0.750=0.500,1.021=0.250;"), "{}", text);
+
+    let back = SmFile::from_string(&text).unwrap();
+    assert_eq!(back.stops, sm.stops);
+    assert_eq!(back.segments.delays, sm.segments.delays);
+    let (row, time) = note_time(&back);
+    assert_eq!(row, Row::from_rows(16));
+    assert!((time - 1000.0 / 3.0).abs() < 1e-9, "{}", time);
+}
+
+#[test]
+fn writes_only_source_tags_and_set_values() {
+    let mut sm = SmFile::from_string("#TITLE:Song;\n#BPMS:0=120;\n#DELAYS:;\n#WARPS:;\n#FAKES:;\n#OFFSET:0;").unwrap();
+    let names = |sm: &SmFile| -> Vec<String> {
+        msd::tokenize(&sm.to_sm_string()).into_iter().map(|tag| tag.name).collect()
+    };
+    // Empty source tags stay, defaults the source did not have are not added
+    assert_eq!(names(&sm), vec!["TITLE", "BPMS", "DELAYS", "WARPS", "FAKES", "OFFSET"]);
+
+    // Values set since are written after the source's tags
+    sm.metadata.genre = "Rock".to_string();
+    sm.metadata.sample_start = 1500.0;
+    let text = sm.to_sm_string();
+    assert!(text.contains("#GENRE:Rock;") && text.contains("#SAMPLESTART:1.500;"), "{}", text);
+    assert_eq!(names(&sm)[6..], ["GENRE", "SAMPLESTART"]);
+}
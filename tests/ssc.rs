@@ -0,0 +1,81 @@
+use rotterna_lib::Error;
+use rotterna_lib::structs::SmFile;
+use rotterna_lib::timing::Row;
+use std::path::PathBuf;
+
+fn split_timing() -> SmFile {
+    SmFile::from_file(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/SplitTiming.ssc")).unwrap()
+}
+
+/// (time in MILLISECONDS, note line) of each non-empty line of `chart`.
+fn notes(sm: &SmFile, chart: usize) -> Vec<(f64, String)> {
+    sm.charts[chart]
+        .measures
+        .iter()
+        .flat_map(|measure| measure.beats.iter())
+        .filter(|beat| beat.notes.iter().any(|note| note.to_char() != '0'))
+        .map(|beat| (beat.time, beat.notes.iter().map(|note| note.to_char()).collect()))
+        .collect()
+}
+
+#[test]
+fn reads_chart_headers() {
+    let sm = split_timing();
+    assert_eq!(sm.metadata.title, "Split Timing");
+    assert_eq!(sm.charts.len(), 2);
+
+    let names: Vec<(&str, &str, &str, u32)> = sm
+        .charts
+        .iter()
+        .map(|chart| (chart.chart_name.as_str(), chart.chart_style.as_str(), chart.difficulty.as_str(), chart.meter))
+        .collect();
+    assert_eq!(names, vec![("Song Timing", "Pad", "Easy", 3), ("Double Time", "Keyboard", "Hard", 8)]);
+    assert!(sm.charts.iter().all(|chart| chart.credit == "rOtterna" && chart.column_count == 4));
+}
+
+#[test]
+fn charts_without_timing_tags_use_the_song_timing() {
+    let sm = split_timing();
+    assert!(sm.charts[0].timing.is_none());
+
+    // Beat 0 plays at 50ms, beats last 500ms
+    let expected = [(50.0, "1000"), (550.0, "0100"), (1050.0, "0010"), (1550.0, "0001")];
+    assert_eq!(notes(&sm, 0), expected.map(|(time, line)| (time, line.to_string())));
+}
+
+#[test]
+fn split_timing_overrides_the_tags_it_has() {
+    let sm = split_timing();
+    let timing = sm.charts[1].timing.as_ref().unwrap();
+    assert_eq!(timing.bpms, vec![(Row::ZERO, 240.0)]);
+    assert_eq!(timing.stops, vec![(Row::from_rows(96), 0.5)]);
+    // No #OFFSET of its own: the song's
+    assert_eq!(timing.offset, -50.0);
+    assert_eq!(sm.bpms, vec![(Row::ZERO, 120.0)]);
+    assert!(sm.stops.is_empty());
+
+    // Beats last 250ms, with a 500ms stop after beat 2; keysounds and attacks are skipped
+    let expected = [
+        (50.0, "1000"),
+        (300.0, "0100"),
+        (550.0, "0010"),
+        (1300.0, "0001"),
+        (1550.0, "2000"),
+        (1800.0, "3000"),
+    ];
+    assert_eq!(notes(&sm, 1), expected.map(|(time, line)| (time, line.to_string())));
+    assert_eq!(sm.charts[1].holds.len(), 1);
+}
+
+#[test]
+fn reads_keysounds_in_sm_files_too() {
+    let sm = SmFile::from_string("#BPMS:0=120;#NOTES:dance-single::Hard:1::\n1[3]00{drunk}2[1]\n0003\n;").unwrap();
+    assert_eq!(notes(&sm, 0)[0].1, "1002");
+
+    // Groups must follow a note and be closed
+    for line in ["[1]1000", "1[1000", "10{00"] {
+        let content = format!("#BPMS:0=120;#NOTES:dance-single::Hard:1::\n{}\n;", line);
+        let err = SmFile::from_string(&content).unwrap_err();
+        assert!(matches!(err, Error::BadNoteLine { .. }), "{}: {:?}", line, err);
+    }
+}
@@ -0,0 +1,269 @@
+use rotterna_lib::Error;
+use rotterna_lib::converter::osu::to_osu;
+use rotterna_lib::structs::{OsuSettings, SmFile};
+use rotterna_lib::timing::{Row, Snap, TimingData};
+
+/// Times of the note lines of the first chart, in MILLISECONDS.
+fn beat_times(sm: &SmFile) -> Vec<f64> {
+    sm.charts[0]
+        .measures
+        .iter()
+        .flat_map(|measure| measure.beats.iter())
+        .map(|beat| beat.time)
+        .collect()
+}
+
+#[test]
+fn stops_delay_the_notes_after_them() {
+    // 120 BPM: a beat is 500ms, the stop on beat 1 lasts 750ms
+    let sm = SmFile::from_string(
+        "#OFFSET:0;#BPMS:0=120;#STOPS:1.000=0.750;\
+         #NOTES:dance-single::Hard:1::\n1000\n0100\n0010\n0001\n,\n1000\n;",
+    )
+    .unwrap();
+    assert_eq!(sm.stops, vec![(Row::from_rows(48), 0.75)]);
+
+    // The note on the stop is hit before it
+    assert_eq!(beat_times(&sm), vec![0.0, 500.0, 1750.0, 2250.0, 2750.0]);
+    assert_eq!(sm.charts[0].measures[1].start_time, 2750.0);
+
+    // A pause at 500ms and the resume at 1250ms
+    let beatmap = to_osu(&sm, &sm.charts[0], &OsuSettings::new()).unwrap();
+    let points: Vec<(f64, f64, bool)> = beatmap
+        .timing_points
+        .iter()
+        .map(|point| (point.time, point.beat_length, point.uninherited))
+        .collect();
+    assert_eq!(
+        points,
+        vec![(0.0, 500.0, true), (500.0, -10000.0, false), (1250.0, 500.0, true)]
+    );
+}
+
+#[test]
+fn positive_offsets_put_beat_zero_before_the_music() {
+    // Beat 0 is at -OFFSET seconds; a negative #OFFSET is after the music starts
+    for (offset, first_ms) in [("0.5", -500.0), ("-0.5", 500.0)] {
+        let sm = SmFile::from_string(&format!(
+            "#OFFSET:{};#BPMS:0=120;#NOTES:dance-single::Hard:1::\n1000\n0100\n0010\n0001\n;",
+            offset
+        ))
+        .unwrap();
+        assert_eq!(sm.offset, -first_ms);
+        let expected: Vec<f64> = (0..4).map(|beat| first_ms + 500.0 * beat as f64).collect();
+        assert_eq!(beat_times(&sm), expected, "{}", offset);
+
+        let beatmap = to_osu(&sm, &sm.charts[0], &OsuSettings::new()).unwrap();
+        let times: Vec<f64> = beatmap.hit_objects.iter().map(|object| object.time as f64).collect();
+        assert_eq!(times, expected, "{}", offset);
+        assert_eq!(beatmap.timing_points[0].time, first_ms, "{}", offset);
+    }
+}
+
+/// A 4-key chart with a tap on each of the first 6 beats.
+fn six_beats(timing_tags: &str) -> SmFile {
+    SmFile::from_string(&format!(
+        "#OFFSET:0;{}#NOTES:dance-single::Hard:1::\n1000\n0100\n0010\n0001\n,\n1000\n0100\n0000\n0000\n;",
+        timing_tags
+    ))
+    .unwrap()
+}
+
+fn judged(sm: &SmFile) -> Vec<bool> {
+    sm.charts[0]
+        .measures
+        .iter()
+        .flat_map(|measure| measure.beats.iter())
+        .filter(|beat| beat.notes.iter().any(|note| note.is_note()))
+        .map(|beat| beat.judged)
+        .collect()
+}
+
+#[test]
+fn delays_pause_before_their_row() {
+    let sm = six_beats("#BPMS:0=120;#DELAYS:1=0.25;#STOPS:2=0.5;");
+    let times: Vec<f64> = beat_times(&sm).into_iter().take(6).collect();
+    assert_eq!(times, vec![0.0, 750.0, 1250.0, 2250.0, 2750.0, 3250.0]);
+    assert_eq!(judged(&sm), vec![true; 6]);
+}
+
+#[test]
+fn warps_skip_rows_instantly() {
+    let sm = six_beats("#BPMS:0=120;#WARPS:1=2;");
+    let timing = TimingData::from_sm(&sm);
+    assert_eq!(timing.warps(), &[(Row::from_rows(48), Some(Row::from_rows(144)))]);
+    assert_eq!(&beat_times(&sm)[..6], &[0.0, 500.0, 500.0, 500.0, 1000.0, 1500.0]);
+    assert_eq!(judged(&sm), vec![true, false, false, true, true, true]);
+}
+
+#[test]
+fn negative_bpms_warp_until_the_time_is_made_up() {
+    // Beat 2 to 3 at -120 BPM rewinds 500ms, which beats 3 to 4 at 120 BPM play again
+    let sm = six_beats("#BPMS:0=120,2=-120,3=120;");
+    let timing = TimingData::from_sm(&sm);
+    assert_eq!(timing.warps(), &[(Row::from_rows(96), Some(Row::from_rows(192)))]);
+    assert_eq!(&beat_times(&sm)[..6], &[0.0, 500.0, 1000.0, 1000.0, 1000.0, 1500.0]);
+    assert_eq!(judged(&sm), vec![true, true, false, false, true, true]);
+
+    // Never made up: the warp never ends
+    let sm = six_beats("#BPMS:0=120,2=-120;");
+    assert_eq!(TimingData::from_sm(&sm).warps(), &[(Row::from_rows(96), None)]);
+    assert_eq!(judged(&sm), vec![true, true, false, false, false, false]);
+}
+
+#[test]
+fn negative_stops_warp_until_the_time_is_made_up() {
+    let sm = six_beats("#BPMS:0=120;#STOPS:2=-0.5;");
+    let timing = TimingData::from_sm(&sm);
+    assert_eq!(timing.warps(), &[(Row::from_rows(96), Some(Row::from_rows(144)))]);
+    assert_eq!(&beat_times(&sm)[..6], &[0.0, 500.0, 1000.0, 1000.0, 1500.0, 2000.0]);
+    assert_eq!(judged(&sm), vec![true, true, false, true, true, true]);
+
+    // A later stop pays the rest back
+    let sm = six_beats("#BPMS:0=120;#STOPS:2=-0.5,2.5=0.75;");
+    assert_eq!(TimingData::from_sm(&sm).warps(), &[(Row::from_rows(96), Some(Row::from_rows(120)))]);
+    assert_eq!(&beat_times(&sm)[..6], &[0.0, 500.0, 1000.0, 1750.0, 2250.0, 2750.0]);
+}
+
+#[test]
+fn stops_in_warps_still_pause() {
+    let sm = six_beats("#BPMS:0=120,2=-120,3=120;#STOPS:2=0.5;");
+    assert_eq!(&beat_times(&sm)[..6], &[0.0, 500.0, 1000.0, 1500.0, 1500.0, 2000.0]);
+    // A row with a stop is never warped
+    assert_eq!(judged(&sm), vec![true, true, true, false, true, true]);
+
+    // The converter pauses without writing the negative BPM
+    let beatmap = to_osu(&sm, &sm.charts[0], &OsuSettings::new()).unwrap();
+    let points: Vec<(f64, f64, bool)> = beatmap
+        .timing_points
+        .iter()
+        .map(|point| (point.time, point.beat_length, point.uninherited))
+        .collect();
+    assert_eq!(
+        points,
+        vec![(0.0, 500.0, true), (1000.0, -10000.0, false), (1500.0, 500.0, true)]
+    );
+}
+
+#[test]
+fn rejects_zero_bpms() {
+    for bpms in ["#BPMS:0=120,4=0;", "#BPMS:0=0.000;", "#BPMS:0=inf;"] {
+        let err = SmFile::from_string(bpms).unwrap_err();
+        assert!(matches!(&err, Error::MalformedPair { tag, .. } if tag == "BPMS"), "{}: {:?}", bpms, err);
+    }
+}
+
+#[test]
+fn rows_are_reduced_fractions() {
+    let third = Row::new(2, 6);
+    assert_eq!((third.numer(), third.denom()), (1, 3));
+    assert_eq!(Row::new(3, -6), Row::new(-1, 2));
+    assert_eq!(Row::new(5, 0), Row::ZERO);
+    assert_eq!(Row::new(96, 2), Row::from_rows(48));
+    assert!(Row::from_rows(48).is_whole() && !third.is_whole());
+
+    // Sums of thirds are exact, where floats would drift
+    let sum = (0..3).fold(Row::ZERO, |sum, _| sum + third);
+    assert_eq!(sum, Row::from_rows(1));
+    assert_eq!(Row::from_rows(1) - third, Row::new(2, 3));
+    assert_eq!(Row::new(1, 2) + Row::new(1, 3), Row::new(5, 6));
+
+    assert!(Row::new(1, 3) < Row::new(1, 2) && Row::new(-1, 2) < Row::ZERO);
+    assert_eq!(Row::new(7, 2).max(Row::from_rows(3)), Row::new(7, 2));
+    assert_eq!((Row::new(7, 2).to_string(), Row::from_rows(-4).to_string()), ("7/2".to_string(), "-4".to_string()));
+    assert_eq!((Row::new(3, 2).as_f64(), Row::from_rows(72).beats()), (1.5, 1.5));
+}
+
+#[test]
+fn rows_of_note_lines_and_measures() {
+    // Line 3 of 7 in measure 2: 2 * 192 + 3 * 192 / 7
+    assert_eq!(Row::in_measure(2, 3, 7), Row::new(2 * 192 * 7 + 3 * 192, 7));
+    assert_eq!(Row::in_measure(1, 4, 16), Row::from_rows(192 + 48));
+    assert_eq!(Row::in_measure(0, 0, 0), Row::ZERO);
+
+    assert_eq!(Row::in_measure(2, 6, 7).measure(), 2);
+    assert_eq!(Row::from_rows(192).measure(), 1);
+    assert_eq!(Row::new(-1, 3).measure(), -1);
+    assert_eq!(Row::from_rows(-192).measure(), -1);
+    assert_eq!(Row::from_rows(-193).measure(), -2);
+}
+
+#[test]
+fn rows_from_floats() {
+    assert_eq!(Row::round(47.5), Row::from_rows(48));
+    assert_eq!(Row::round(-0.4), Row::ZERO);
+    assert_eq!(Row::approximate(10.0 / 3.0), Row::new(3333, 1000));
+    assert_eq!(Row::approximate(-0.0004), Row::ZERO);
+    assert_eq!(Row::approximate(2.5), Row::new(5, 2));
+}
+
+#[test]
+fn beats_parse_to_exact_rows() {
+    let parse = |text: &str| Row::parse_beat(text).unwrap();
+    assert_eq!(parse("12.500"), Row::from_rows(600));
+    assert_eq!(parse(" -1 "), Row::from_rows(-48));
+    assert_eq!(parse("+2"), Row::from_rows(96));
+    assert_eq!(parse(".25"), Row::from_rows(12));
+    assert_eq!(parse("7."), Row::from_rows(336));
+    assert_eq!(parse("0.001"), Row::new(48, 1000));
+    // Rounded decimals of whole rows read as the row
+    assert_eq!(parse("10.333"), Row::from_rows(496));
+    assert_eq!(parse("-0.667"), Row::from_rows(-32));
+    assert_eq!(parse("0.020833"), Row::from_rows(1));
+    // Digits past the ninth decimal are dropped
+    assert_eq!(parse("0.0000000019"), Row::new(48, 1_000_000_000));
+    // Other float syntaxes are rounded to a row
+    assert_eq!(parse("1e1"), Row::from_rows(480));
+    assert_eq!(Row::parse_beat("beat"), None);
+    assert_eq!(Row::parse_beat("."), None);
+}
+
+#[test]
+fn rows_snap_to_the_coarsest_division() {
+    let snaps = [
+        (0, Snap::Fourth),
+        (48, Snap::Fourth),
+        (24, Snap::Eighth),
+        (16, Snap::Twelfth),
+        (12, Snap::Sixteenth),
+        (8, Snap::TwentyFourth),
+        (6, Snap::ThirtySecond),
+        (4, Snap::FortyEighth),
+        (3, Snap::SixtyFourth),
+        (1, Snap::HundredNinetySecond),
+        (-24, Snap::Eighth), // Rows before 0 snap from their own measure
+    ];
+    for (rows, snap) in snaps {
+        assert_eq!(Row::from_rows(rows).snap(), Some(snap), "{}", rows);
+        assert_eq!(Row::from_rows(192 * 3 + rows).snap(), Some(snap), "{}", rows);
+    }
+    assert_eq!(Row::new(1, 2).snap(), None);
+
+    let lines: Vec<u32> = Snap::ALL.iter().map(|snap| snap.per_measure()).collect();
+    assert_eq!(lines, vec![4, 8, 12, 16, 24, 32, 48, 64, 192]);
+    assert!(Snap::ALL.iter().all(|snap| snap.rows() * snap.per_measure() == 192));
+    assert!(Snap::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn note_lines_have_their_snap() {
+    // A 12th measure, then a 7-line measure whose lines are between 192nds
+    let sm = SmFile::from_string(&format!(
+        "#BPMS:0=120;#NOTES:dance-single::Hard:1::\n{}\n,\n{}\n;",
+        ["1000", "0100", "0010"].repeat(4).join("\n"),
+        ["1000"; 7].join("\n")
+    ))
+    .unwrap();
+    let snaps: Vec<Option<Snap>> = sm.charts[0]
+        .measures
+        .iter()
+        .flat_map(|measure| measure.beats.iter())
+        .map(|beat| beat.snap())
+        .collect();
+
+    let twelfths = [Snap::Fourth, Snap::Twelfth, Snap::Twelfth].repeat(4);
+    let mut expected: Vec<Option<Snap>> = twelfths.into_iter().map(Some).collect();
+    expected.push(Some(Snap::Fourth));
+    expected.extend([None; 6]);
+    assert_eq!(snaps, expected);
+}